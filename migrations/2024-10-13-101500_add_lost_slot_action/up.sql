@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TYPE EventUserAction ADD VALUE 'lost_slot';
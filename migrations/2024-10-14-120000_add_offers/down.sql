@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "event_user"
+DROP COLUMN "offer_expires";
+
+ALTER TABLE "event"
+DROP COLUMN "offer_hours";
+
+ALTER TABLE "event"
+DROP COLUMN "offer_mode";
+
+ALTER TYPE EventUserAction DROP VALUE 'offered';
+ALTER TYPE EventUserAction DROP VALUE 'offer_confirmed';
+ALTER TYPE EventUserAction DROP VALUE 'offer_expired';
+
+ALTER TYPE EventUserState DROP VALUE 'offered';
+ALTER TYPE EventUserState DROP VALUE 'offered_new';
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+ALTER TYPE EventUserState ADD VALUE 'offered';
+ALTER TYPE EventUserState ADD VALUE 'offered_new';
+
+ALTER TYPE EventUserAction ADD VALUE 'offered';
+ALTER TYPE EventUserAction ADD VALUE 'offer_confirmed';
+ALTER TYPE EventUserAction ADD VALUE 'offer_expired';
+
+ALTER TABLE "event"
+ADD "offer_mode" BOOL NOT NULL DEFAULT FALSE;
+
+ALTER TABLE "event"
+ADD "offer_hours" INT NOT NULL DEFAULT 24;
+
+ALTER TABLE "event_user"
+ADD "offer_expires" TIMESTAMP;
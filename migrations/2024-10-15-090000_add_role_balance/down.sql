@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "event"
+DROP COLUMN "balance_roles";
+
+ALTER TABLE "event"
+DROP COLUMN "target_role_factor";
+
+ALTER TABLE "event"
+DROP COLUMN "role_tolerance";
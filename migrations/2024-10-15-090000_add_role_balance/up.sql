@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE "event"
+ADD "balance_roles" BOOL NOT NULL DEFAULT FALSE;
+
+ALTER TABLE "event"
+ADD "target_role_factor" FLOAT NOT NULL DEFAULT 50;
+
+ALTER TABLE "event"
+ADD "role_tolerance" FLOAT NOT NULL DEFAULT 10;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE "event_guest"(
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "event_user_id" INT NOT NULL REFERENCES event_user(id),
+    "name" TEXT NOT NULL,
+    "user_id" INT REFERENCES users(id),
+    "attended" BOOL NOT NULL DEFAULT FALSE
+);
+
+INSERT INTO "event_guest" ("event_user_id", "name")
+SELECT "event_user"."id", 'Gast ' || "number"
+FROM "event_user", generate_series(1, "event_user"."guests") AS "number";
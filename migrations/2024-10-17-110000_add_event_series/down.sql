@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX "event_series_occurrence";
+
+ALTER TABLE "event"
+DROP COLUMN "series_id",
+DROP COLUMN "occurrence_date";
+
+DROP TABLE "event_series_exception";
+DROP TABLE "event_series";
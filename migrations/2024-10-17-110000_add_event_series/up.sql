@@ -0,0 +1,29 @@
+-- Your SQL goes here
+CREATE TABLE "event_series"(
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "first_date" TIMESTAMP NOT NULL,
+    "end_date" TIMESTAMP,
+    "interval_days" INT NOT NULL,
+    "generate_days_ahead" INT NOT NULL,
+
+    "visible_offset_hours" INT NOT NULL,
+    "register_deadline_offset_hours" INT NOT NULL,
+    "archive_offset_hours" INT NOT NULL,
+
+    "slots" INT NOT NULL,
+    "new_slots" INT NOT NULL,
+    "custom_workshop" TEXT NOT NULL,
+    "workshop_file" TEXT NOT NULL
+);
+
+CREATE TABLE "event_series_exception"(
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "series_id" INT NOT NULL REFERENCES event_series(id),
+    "date" TIMESTAMP NOT NULL
+);
+
+ALTER TABLE "event"
+ADD "series_id" INT REFERENCES event_series(id),
+ADD "occurrence_date" TIMESTAMP;
+
+CREATE UNIQUE INDEX "event_series_occurrence" ON "event" ("series_id", "occurrence_date");
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "event"
+DROP COLUMN "cancelled";
+
+ALTER TYPE EventUserAction DROP VALUE 'event_cancelled';
+
+ALTER TYPE EventUserState DROP VALUE 'cancelled';
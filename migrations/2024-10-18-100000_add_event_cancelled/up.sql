@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE "event"
+ADD "cancelled" BOOL NOT NULL DEFAULT FALSE;
+
+ALTER TYPE EventUserAction ADD VALUE 'event_cancelled';
+
+ALTER TYPE EventUserState ADD VALUE 'cancelled';
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE TABLE "calendar_token"(
+    "user_id" INT PRIMARY KEY NOT NULL REFERENCES users(id),
+    "token_hash" TEXT NOT NULL UNIQUE
+);
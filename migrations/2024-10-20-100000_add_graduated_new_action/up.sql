@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TYPE EventUserAction ADD VALUE 'graduated_new';
@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "event"
+DROP COLUMN "lottery_drawn";
+
+ALTER TABLE "event"
+DROP COLUMN "lottery_seed";
+
+ALTER TABLE "event"
+DROP COLUMN "lottery_loser_weight";
+
+ALTER TABLE "event"
+DROP COLUMN "lottery_mode";
+
+ALTER TYPE EventUserAction DROP VALUE 'lottery_lost';
+ALTER TYPE EventUserAction DROP VALUE 'lottery_won';
+
+ALTER TYPE EventUserState DROP VALUE 'lottery';
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+ALTER TYPE EventUserState ADD VALUE 'lottery';
+
+ALTER TYPE EventUserAction ADD VALUE 'lottery_won';
+ALTER TYPE EventUserAction ADD VALUE 'lottery_lost';
+
+ALTER TABLE "event"
+ADD "lottery_mode" BOOL NOT NULL DEFAULT FALSE;
+
+ALTER TABLE "event"
+ADD "lottery_loser_weight" FLOAT8 NOT NULL DEFAULT 0;
+
+ALTER TABLE "event"
+ADD "lottery_seed" INT8;
+
+ALTER TABLE "event"
+ADD "lottery_drawn" TIMESTAMP;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TYPE EventPermissionType AS ENUM ('organizer', 'check_attended');
+
+CREATE TABLE "event_permission"(
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "user_id" INT NOT NULL REFERENCES users(id),
+    "event_id" INT NOT NULL REFERENCES event(id),
+    "permission_type" EventPermissionType NOT NULL,
+    UNIQUE ("user_id", "event_id", "permission_type")
+);
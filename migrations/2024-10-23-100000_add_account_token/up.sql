@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TYPE AccountTokenKind AS ENUM ('password_reset');
+
+CREATE TABLE "account_token"(
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "user_id" INT NOT NULL REFERENCES users(id),
+    "kind" AccountTokenKind NOT NULL,
+    "token_hash" TEXT NOT NULL UNIQUE,
+    "expires" TIMESTAMP NOT NULL
+);
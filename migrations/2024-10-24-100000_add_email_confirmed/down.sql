@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "users"
+DROP COLUMN "email_confirmed";
+
+DELETE FROM "account_token" WHERE "kind" = 'email_verification';
+ALTER TYPE AccountTokenKind DROP VALUE 'email_verification';
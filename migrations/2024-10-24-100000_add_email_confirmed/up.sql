@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TYPE AccountTokenKind ADD VALUE 'email_verification';
+
+ALTER TABLE "users"
+ADD "email_confirmed" BOOL NOT NULL DEFAULT FALSE;
+
+-- Existing accounts were created before verification existed.
+UPDATE "users" SET "email_confirmed" = TRUE;
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM "account_token" WHERE "kind" = 'email_change';
+
+ALTER TABLE "account_token"
+DROP COLUMN "data";
+
+ALTER TYPE AccountTokenKind DROP VALUE 'email_change';
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TYPE AccountTokenKind ADD VALUE 'email_change';
+
+ALTER TABLE "account_token"
+ADD "data" TEXT;
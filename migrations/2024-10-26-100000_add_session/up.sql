@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE "session" (
+    "id" TEXT PRIMARY KEY NOT NULL,
+    "data" TEXT NOT NULL,
+    "expiry_date" TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX "session_expiry_date_idx" ON "session" ("expiry_date");
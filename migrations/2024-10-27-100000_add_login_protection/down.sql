@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "users"
+DROP COLUMN "locked_until";
+
+DROP TABLE "login_failure";
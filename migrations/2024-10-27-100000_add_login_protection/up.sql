@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "login_failure" (
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "email" TEXT NOT NULL,
+    "address" TEXT NOT NULL,
+    "date" TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "login_failure_email_idx" ON "login_failure" ("email", "date");
+CREATE INDEX "login_failure_address_idx" ON "login_failure" ("address", "date");
+
+ALTER TABLE "users"
+ADD "locked_until" TIMESTAMP;
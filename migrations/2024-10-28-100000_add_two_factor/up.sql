@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "user_totp" (
+    "user_id" INT PRIMARY KEY NOT NULL REFERENCES users(id),
+    "secret" TEXT NOT NULL,
+    "enabled" BOOL NOT NULL DEFAULT FALSE,
+    "last_step" INT8
+);
+
+CREATE TABLE "totp_recovery_code" (
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "user_id" INT NOT NULL REFERENCES users(id),
+    "code_hash" TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "api_token_permission";
+DROP TABLE "api_token";
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE "api_token" (
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "user_id" INT NOT NULL REFERENCES users(id),
+    "name" TEXT NOT NULL,
+    "token_hash" TEXT NOT NULL UNIQUE,
+    "created" TIMESTAMP NOT NULL,
+    "expires" TIMESTAMP,
+    "last_used" TIMESTAMP
+);
+
+CREATE TABLE "api_token_permission" (
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "token_id" INT NOT NULL REFERENCES api_token(id) ON DELETE CASCADE,
+    "user_permission" UserPermission NOT NULL
+);
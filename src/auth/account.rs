@@ -0,0 +1,141 @@
+use axum::Router;
+use axum::Json;
+use axum::routing::post;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use tracing::warn;
+use utoipa::ToSchema;
+use crate::auth::{get_user_with_email, hash_password, verify_password, AuthSession, User};
+use crate::auth::tokens::{consume_account_token_with_data, create_account_token_with_data, AccountTokenKind};
+use crate::backend::{Backend, DBConnection};
+use crate::config::{email_change_token_hours, frontend_url};
+use crate::error::{APIError, APIResult};
+use crate::mails::{send_email_change_mail, send_email_change_notice_mail};
+use crate::schema::users;
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct ChangePassword {
+    pub password: String,
+    pub new_password: String,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct ChangeEmail {
+    pub password: String,
+    pub new_email: String,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct ConfirmEmailChange {
+    pub token: String,
+}
+
+pub fn auth_to_user_with_password(auth: &AuthSession, password: &str) -> APIResult<User> {
+    let Some(user) = auth.user.clone() else {
+        return Err(APIError::UNAUTHORIZED)
+    };
+
+    if !verify_password(&user, password) {
+        return Err(APIError::InvalidCredentials)
+    }
+
+    Ok(user)
+}
+
+/// Sets a new password. The session is bound to the password hash, so all other sessions of the user
+/// end and the current one is logged in again with the new hash.
+#[utoipa::path(
+    post,
+    path = "/user/password/change"
+)]
+pub async fn change_password(
+    mut auth: AuthSession,
+    Json(change): Json<ChangePassword>
+) -> APIResult<()> {
+    let user = auth_to_user_with_password(&auth, &change.password)?;
+    let pw_hash = hash_password(&change.new_password)?;
+
+    let mut conn = auth.backend.get_connection().await?;
+    let user = diesel::update(users::table)
+        .filter(users::id.eq(user.id))
+        .set(users::pw_hash.eq(pw_hash))
+        .returning(User::as_select())
+        .get_result::<User>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    drop(conn);
+
+    auth.login(&user)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(())
+}
+
+/// Sends a confirmation link to the new email and a notice to the old one.
+/// The email only changes once the link is used.
+#[utoipa::path(
+    post,
+    path = "/user/email/change"
+)]
+pub async fn change_email(
+    auth: AuthSession,
+    Json(change): Json<ChangeEmail>
+) -> APIResult<()> {
+    let user = auth_to_user_with_password(&auth, &change.password)?;
+
+    let mut conn = auth.backend.get_connection().await?;
+    if get_user_with_email(&mut conn, &change.new_email).await.is_some() {
+        return Err(APIError::EmailUsed)
+    }
+
+    let token = create_account_token_with_data(user.id, AccountTokenKind::EmailChange, email_change_token_hours(), Some(change.new_email.clone()), &mut conn).await?;
+    let url = format!("{}/confirm_email_change?token={token}", frontend_url());
+
+    tokio::spawn(async move {
+        if let Err(err) = send_email_change_mail(&change.new_email, &url).await {
+            warn!("Could not send email change mail to user {}: {err}", user.id);
+        }
+        if let Err(err) = send_email_change_notice_mail(&user.email, &change.new_email).await {
+            warn!("Could not send email change notice to user {}: {err}", user.id);
+        }
+    });
+
+    Ok(())
+}
+
+/// Confirming the link also proves the new email, so it counts as confirmed.
+#[utoipa::path(
+    post,
+    path = "/user/email/change/confirm"
+)]
+pub async fn confirm_email_change(
+    mut conn: DBConnection,
+    Json(confirm): Json<ConfirmEmailChange>
+) -> APIResult<()> {
+    let (u_id, new_email) = consume_account_token_with_data(&confirm.token, AccountTokenKind::EmailChange, &mut conn).await?;
+    let new_email = new_email.ok_or(APIError::InvalidToken)?;
+
+    // The email might have been taken since the change was requested.
+    if get_user_with_email(&mut conn, &new_email).await.is_some() {
+        return Err(APIError::EmailUsed)
+    }
+
+    diesel::update(users::table)
+        .filter(users::id.eq(u_id))
+        .set((
+            users::email.eq(new_email),
+            users::email_confirmed.eq(true),
+        ))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(())
+}
+
+pub fn add_account_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/user/password/change", post(change_password))
+        .route("/user/email/change", post(change_email))
+        .route("/user/email/change/confirm", post(confirm_email_change))
+}
@@ -0,0 +1,242 @@
+use std::collections::HashSet;
+use axum::extract::{Path, Request};
+use axum::{Json, Router};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum_login::AuthnBackend;
+use chrono::{Local, NaiveDateTime};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use http::header::AUTHORIZATION;
+use scoped_futures::ScopedFutureExt;
+use utoipa::ToSchema;
+use crate::auth::{AuthSession, User};
+use crate::auth::tokens::hash_token;
+use crate::auth::util::generate_token;
+use crate::backend::{Backend, DBConnection};
+use crate::error::{APIError, APIResult};
+use crate::permissions::{has_permission, UserPermission};
+use crate::schema::{api_token, api_token_permission};
+
+tokio::task_local! {
+    static API_TOKEN_SCOPE: Vec<UserPermission>;
+}
+
+/// Permissions of the API token the current request was authenticated with, `None` for session logins.
+pub fn api_token_scope() -> Option<Vec<UserPermission>> {
+    API_TOKEN_SCOPE.try_with(|scope| scope.clone()).ok()
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct NewApiToken {
+    pub name: String,
+    pub permissions: Vec<UserPermission>,
+    pub expires: Option<NaiveDateTime>,
+}
+
+/// The token is only shown once, only its hash is stored.
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct CreatedApiToken {
+    pub id: i32,
+    pub token: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct ApiToken {
+    pub id: i32,
+    pub name: String,
+    pub permissions: Vec<UserPermission>,
+    pub created: NaiveDateTime,
+    pub expires: Option<NaiveDateTime>,
+    pub last_used: Option<NaiveDateTime>,
+}
+
+async fn get_api_token_permissions(token_id: i32, conn: &mut DBConnection) -> APIResult<Vec<UserPermission>> {
+    api_token_permission::table
+        .filter(api_token_permission::token_id.eq(token_id))
+        .select(api_token_permission::user_permission)
+        .get_results::<UserPermission>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)
+}
+
+/// Returns the owner of the token and the permissions the token may use.
+async fn authenticate_api_token(backend: &Backend, token: &str) -> APIResult<(User, Vec<UserPermission>)> {
+    let mut conn = backend.get_connection().await?;
+    let now = Local::now().naive_local();
+
+    let (token_id, u_id) = diesel::update(api_token::table)
+        .filter(api_token::token_hash.eq(hash_token(token)))
+        .filter(api_token::expires.is_null().or(api_token::expires.gt(now)))
+        .set(api_token::last_used.eq(now))
+        .returning((api_token::id, api_token::user_id))
+        .get_result::<(i32, i32)>(&mut conn.0)
+        .await
+        .optional()
+        .map_err(APIError::internal)?
+        .ok_or(APIError::UNAUTHORIZED)?;
+
+    let scope = get_api_token_permissions(token_id, &mut conn).await?;
+    drop(conn);
+
+    let user = backend.get_user(&u_id)
+        .await?
+        .ok_or(APIError::UNAUTHORIZED)?;
+
+    Ok((user, scope))
+}
+
+/// Logs in requests with an `Authorization: Bearer` token for this request only.
+/// Has to run inside the auth layer, so the `AuthSession` is already in the request.
+pub async fn api_token_auth(mut request: Request, next: Next) -> Response {
+    let token = request.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+
+    let Some(token) = token else {
+        return next.run(request).await
+    };
+
+    let Some(auth) = request.extensions_mut().get_mut::<AuthSession>() else {
+        return APIError::Internal("Auth session missing".to_string()).into_response()
+    };
+
+    match authenticate_api_token(&auth.backend, &token).await {
+        Ok((user, scope)) => {
+            auth.user = Some(user);
+            API_TOKEN_SCOPE.scope(scope, next.run(request)).await
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Tokens can only be managed from a session login, so a leaked token can not create further tokens.
+fn auth_to_session_user(auth: &AuthSession) -> APIResult<User> {
+    if api_token_scope().is_some() {
+        return Err(APIError::UNAUTHORIZED)
+    }
+    auth.user.clone().ok_or(APIError::UNAUTHORIZED)
+}
+
+pub async fn delete_api_tokens_of_user(u_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    // The permissions of the tokens are deleted by the cascade.
+    diesel::delete(api_token::table)
+        .filter(api_token::user_id.eq(u_id))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    Ok(())
+}
+
+/// Creates a token for the logged-in user. The token can only use permissions the user has.
+#[utoipa::path(
+    post,
+    path = "/user/api_tokens/create"
+)]
+pub async fn create_api_token(
+    auth: AuthSession,
+    Json(new_token): Json<NewApiToken>,
+) -> APIResult<Json<CreatedApiToken>> {
+    let user = auth_to_session_user(&auth)?;
+    let mut conn = auth.backend.get_connection().await?;
+
+    let permissions = new_token.permissions.into_iter().collect::<HashSet<_>>();
+    for permission in permissions.iter().copied() {
+        if !has_permission(&mut conn, user.id, permission).await {
+            return Err(APIError::PermissionNotThere)
+        }
+    }
+
+    let token = generate_token();
+    let token_hash = hash_token(&token);
+
+    let id = conn.transaction(|conn| async move {
+        let id = diesel::insert_into(api_token::table)
+            .values((
+                api_token::user_id.eq(user.id),
+                api_token::name.eq(new_token.name),
+                api_token::token_hash.eq(token_hash),
+                api_token::created.eq(Local::now().naive_local()),
+                api_token::expires.eq(new_token.expires),
+            ))
+            .returning(api_token::id)
+            .get_result::<i32>(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        for permission in permissions {
+            diesel::insert_into(api_token_permission::table)
+                .values((
+                    api_token_permission::token_id.eq(id),
+                    api_token_permission::user_permission.eq(permission),
+                ))
+                .execute(&mut conn.0)
+                .await
+                .map_err(APIError::internal)?;
+        }
+
+        Ok(id)
+    }.scope_boxed()).await?;
+
+    Ok(Json(CreatedApiToken { id, token }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/api_tokens"
+)]
+pub async fn get_api_tokens(
+    auth: AuthSession,
+) -> APIResult<Json<Vec<ApiToken>>> {
+    let user = auth_to_session_user(&auth)?;
+    let mut conn = auth.backend.get_connection().await?;
+
+    let tokens = api_token::table
+        .filter(api_token::user_id.eq(user.id))
+        .order(api_token::created.asc())
+        .select((api_token::id, api_token::name, api_token::created, api_token::expires, api_token::last_used))
+        .get_results::<(i32, String, NaiveDateTime, Option<NaiveDateTime>, Option<NaiveDateTime>)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let mut api_tokens = vec![];
+    for (id, name, created, expires, last_used) in tokens {
+        let permissions = get_api_token_permissions(id, &mut conn).await?;
+        api_tokens.push(ApiToken { id, name, permissions, created, expires, last_used });
+    }
+
+    Ok(Json(api_tokens))
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/api_tokens/{id}/revoke"
+)]
+pub async fn revoke_api_token(
+    auth: AuthSession,
+    Path(token_id): Path<i32>,
+) -> APIResult<()> {
+    let user = auth_to_session_user(&auth)?;
+    let mut conn = auth.backend.get_connection().await?;
+
+    diesel::delete(api_token::table)
+        .filter(api_token::id.eq(token_id))
+        .filter(api_token::user_id.eq(user.id))
+        .returning(api_token::id)
+        .get_result::<i32>(&mut conn.0)
+        .await
+        .optional()
+        .map_err(APIError::internal)?
+        .ok_or(APIError::ApiTokenNotFound)?;
+
+    Ok(())
+}
+
+pub fn add_api_token_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/user/api_tokens", get(get_api_tokens))
+        .route("/user/api_tokens/create", post(create_api_token))
+        .route("/user/api_tokens/:id/revoke", post(revoke_api_token))
+}
@@ -0,0 +1,84 @@
+use axum::Router;
+use axum::Json;
+use axum::routing::post;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use tracing::warn;
+use utoipa::ToSchema;
+use crate::auth::AuthSession;
+use crate::auth::tokens::{consume_account_token, create_account_token, AccountTokenKind};
+use crate::backend::{Backend, DBConnection};
+use crate::config::{email_verification_token_hours, frontend_url};
+use crate::error::{APIError, APIResult};
+use crate::mails::send_email_verification_mail;
+use crate::schema::users;
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct VerifyEmail {
+    pub token: String,
+}
+
+async fn send_verification_link(u_id: i32, email: &str, conn: &mut DBConnection) -> APIResult<()> {
+    let token = create_account_token(u_id, AccountTokenKind::EmailVerification, email_verification_token_hours(), conn).await?;
+    let url = format!("{}/verify_email?token={token}", frontend_url());
+
+    send_email_verification_mail(email, &url).await
+}
+
+/// Sends the verification mail in the background, so a slow mail server does not hold up the request.
+pub fn spawn_verification_mail(u_id: i32, email: String, mut conn: DBConnection) {
+    tokio::spawn(async move {
+        if let Err(err) = send_verification_link(u_id, &email, &mut conn).await {
+            warn!("Could not send verification mail to user {u_id}: {err}");
+        }
+    });
+}
+
+pub async fn set_email_confirmed(u_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    diesel::update(users::table)
+        .filter(users::id.eq(u_id))
+        .set(users::email_confirmed.eq(true))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/email/verify"
+)]
+pub async fn verify_email(
+    mut conn: DBConnection,
+    Json(verify): Json<VerifyEmail>
+) -> APIResult<()> {
+    let u_id = consume_account_token(&verify.token, AccountTokenKind::EmailVerification, &mut conn).await?;
+    set_email_confirmed(u_id, &mut conn).await
+}
+
+/// Sends a new verification link to the logged-in user. Older links stop working.
+#[utoipa::path(
+    post,
+    path = "/email/verify/resend"
+)]
+pub async fn resend_verification_email(
+    auth: AuthSession,
+) -> APIResult<()> {
+    let Some(user) = auth.user else {
+        return Err(APIError::UNAUTHORIZED)
+    };
+
+    if user.email_confirmed {
+        return Err(APIError::EmailAlreadyConfirmed)
+    }
+
+    let conn = auth.backend.get_connection().await?;
+    spawn_verification_mail(user.id, user.email, conn);
+
+    Ok(())
+}
+
+pub fn add_email_verification_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/email/verify", post(verify_email))
+        .route("/email/verify/resend", post(resend_verification_email))
+}
@@ -0,0 +1,207 @@
+use std::net::SocketAddr;
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts, Path};
+use axum::Router;
+use axum::routing::post;
+use chrono::{Duration, Local, NaiveDateTime};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use http::request::Parts;
+use tracing::warn;
+use crate::backend::{Backend, DBConnection};
+use crate::config::{login_address_free_attempts, login_backoff_base_secs, login_backoff_max_secs, login_failure_window_minutes, login_free_attempts, login_lockout_minutes, login_lockout_threshold, trust_forwarded_for};
+use crate::error::{APIError, APIResult};
+use crate::mails::send_account_locked_mail;
+use crate::schema::{login_failure, users};
+
+/// Address of the client, taken from `X-Forwarded-For` if the proxy is trusted.
+/// The last entry is the one our proxy added, earlier entries are sent by the client and can be forged.
+pub struct ClientAddress(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientAddress
+    where
+        S: Send + Sync,
+{
+    type Rejection = APIError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> APIResult<Self> {
+        if trust_forwarded_for() {
+            let forwarded = parts.headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next_back())
+                .map(|value| value.trim().to_string());
+
+            if let Some(address) = forwarded {
+                return Ok(ClientAddress(address))
+            }
+        }
+
+        let address = parts.extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        Ok(ClientAddress(address))
+    }
+}
+
+/// Seconds to wait after the last failure. Free attempts wait nothing, then the wait doubles with every failure.
+fn backoff_secs(failures: i64, free_attempts: i64) -> i64 {
+    if failures < free_attempts {
+        return 0
+    }
+
+    let doublings = (failures - free_attempts).min(20) as u32;
+    (login_backoff_base_secs() << doublings).min(login_backoff_max_secs())
+}
+
+fn check_backoff(failures: i64, last_failure: Option<NaiveDateTime>, free_attempts: i64, now: NaiveDateTime) -> APIResult<()> {
+    let Some(last_failure) = last_failure else {
+        return Ok(())
+    };
+
+    let retry_at = last_failure + Duration::seconds(backoff_secs(failures, free_attempts));
+    if retry_at > now {
+        return Err(APIError::TooManyAttempts((retry_at - now).num_seconds().max(1)))
+    }
+
+    Ok(())
+}
+
+enum FailureKey<'a> {
+    Email(&'a str),
+    Address(&'a str),
+}
+
+/// Number of failures and the time of the last one in the window.
+async fn get_recent_failures(key: FailureKey<'_>, conn: &mut DBConnection) -> APIResult<(i64, Option<NaiveDateTime>)> {
+    let since = Local::now().naive_local() - Duration::minutes(login_failure_window_minutes());
+
+    let query = login_failure::table
+        .filter(login_failure::date.gt(since))
+        .select((count_star(), diesel::dsl::max(login_failure::date)))
+        .into_boxed();
+
+    let query = match key {
+        FailureKey::Email(email) => query.filter(login_failure::email.eq(email)),
+        FailureKey::Address(address) => query.filter(login_failure::address.eq(address)),
+    };
+
+    query
+        .get_result::<(i64, Option<NaiveDateTime>)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)
+}
+
+/// Fails with `TooManyAttempts` while the account is locked or the email or address has to back off.
+pub async fn check_login_allowed(email: &str, address: &str, conn: &mut DBConnection) -> APIResult<()> {
+    let now = Local::now().naive_local();
+
+    let locked_until = users::table
+        .filter(users::email.eq(email))
+        .select(users::locked_until)
+        .get_result::<Option<NaiveDateTime>>(&mut conn.0)
+        .await
+        .optional()
+        .map_err(APIError::internal)?
+        .flatten();
+
+    if let Some(locked_until) = locked_until {
+        if locked_until > now {
+            return Err(APIError::TooManyAttempts((locked_until - now).num_seconds().max(1)))
+        }
+    }
+
+    let (failures, last_failure) = get_recent_failures(FailureKey::Email(email), conn).await?;
+    check_backoff(failures, last_failure, login_free_attempts(), now)?;
+
+    let (failures, last_failure) = get_recent_failures(FailureKey::Address(address), conn).await?;
+    check_backoff(failures, last_failure, login_address_free_attempts(), now)?;
+
+    Ok(())
+}
+
+/// Records the failure and locks the account once the email reached the threshold.
+/// Locking starts the count for the email anew, so the backoff does not carry over the lockout.
+pub async fn record_login_failure(email: &str, address: &str, conn: &mut DBConnection) -> APIResult<()> {
+    let now = Local::now().naive_local();
+
+    diesel::delete(login_failure::table)
+        .filter(login_failure::date.le(now - Duration::minutes(login_failure_window_minutes())))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    diesel::insert_into(login_failure::table)
+        .values((
+            login_failure::email.eq(email),
+            login_failure::address.eq(address),
+            login_failure::date.eq(now),
+        ))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let (failures, _) = get_recent_failures(FailureKey::Email(email), conn).await?;
+    if failures < login_lockout_threshold() {
+        return Ok(())
+    }
+
+    let locked_until = now + Duration::minutes(login_lockout_minutes());
+    let locked = diesel::update(users::table)
+        .filter(users::email.eq(email))
+        .set(users::locked_until.eq(locked_until))
+        .returning(users::id)
+        .get_result::<i32>(&mut conn.0)
+        .await
+        .optional()
+        .map_err(APIError::internal)?;
+
+    clear_login_failures(email, conn).await?;
+
+    if let Some(u_id) = locked {
+        let email = email.to_string();
+        tokio::spawn(async move {
+            if let Err(err) = send_account_locked_mail(&email, &locked_until).await {
+                warn!("Could not send lockout mail to user {u_id}: {err}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+pub async fn clear_login_failures(email: &str, conn: &mut DBConnection) -> APIResult<()> {
+    diesel::delete(login_failure::table)
+        .filter(login_failure::email.eq(email))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/{id}/unlock"
+)]
+pub async fn unlock_user(
+    mut conn: DBConnection,
+    Path(u_id): Path<i32>,
+) -> APIResult<()> {
+    let email = diesel::update(users::table)
+        .filter(users::id.eq(u_id))
+        .set(users::locked_until.eq(None::<NaiveDateTime>))
+        .returning(users::email)
+        .get_result::<String>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    clear_login_failures(&email, &mut conn).await
+}
+
+pub fn add_admin_login_protection_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/user/:id/unlock", post(unlock_user))
+}
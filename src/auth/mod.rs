@@ -1,8 +1,18 @@
 pub mod routes;
 pub mod util;
-
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
+pub mod tokens;
+pub mod password_reset;
+pub mod email_verification;
+pub mod account;
+pub mod login_protection;
+pub mod two_factor;
+pub mod api_tokens;
+
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
 use axum_login::{AuthnBackend, AuthUser, UserId};
 use diesel::{ExpressionMethods, Insertable, Queryable, QueryDsl, Selectable, SelectableHelper};
 use diesel_async::RunQueryDsl;
@@ -18,6 +28,8 @@ pub struct User {
     pub id: i32,
     pub email: String,
     pub pw_hash: String,
+    pub email_confirmed: bool,
+    pub locked_until: Option<NaiveDateTime>,
 }
 
 
@@ -46,6 +58,22 @@ impl AuthUser for User {
     }
 }
 
+pub fn hash_password(password: &str) -> APIResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let pw_hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(APIError::internal)?
+        .to_string();
+    Ok(pw_hash)
+}
+
+pub fn verify_password(user: &User, password: &str) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(&user.pw_hash) else {
+        return false
+    };
+    Argon2::default().verify_password(password.as_bytes(), &parsed_hash).is_ok()
+}
+
 async fn get_user_with_email(conn: &mut DBConnection, email: &str) -> Option<User> {
     users::table
         .filter(users::columns::email.eq(email))
@@ -67,8 +95,7 @@ impl AuthnBackend for Backend {
         if user.is_none() { return Ok(None) }
         let user = user.unwrap();
         
-        let parsed_hash = PasswordHash::new(&user.pw_hash).unwrap();
-        return if Argon2::default().verify_password(credentials.password.as_bytes(), &parsed_hash).is_ok() {
+        return if verify_password(&user, &credentials.password) {
             Ok(Some(user))
         } else {
             Ok(None)
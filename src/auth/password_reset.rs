@@ -0,0 +1,92 @@
+use axum::Router;
+use axum::Json;
+use axum::routing::post;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use scoped_futures::ScopedFutureExt;
+use tracing::warn;
+use utoipa::ToSchema;
+use crate::auth::{get_user_with_email, hash_password};
+use crate::auth::api_tokens::delete_api_tokens_of_user;
+use crate::auth::tokens::{consume_account_token, create_account_token, AccountTokenKind};
+use crate::backend::{Backend, DBConnection};
+use crate::config::{frontend_url, password_reset_token_hours};
+use crate::error::{APIError, APIResult};
+use crate::mails::send_password_reset_mail;
+use crate::schema::users;
+use crate::session_store::delete_sessions_of_user;
+use crate::user_data::get_user_data_by_id;
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct ForgotPassword {
+    pub email: String,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct ResetPassword {
+    pub token: String,
+    pub password: String,
+}
+
+async fn send_reset_link(u_id: i32, email: &str, conn: &mut DBConnection) -> APIResult<()> {
+    let user_data = get_user_data_by_id(conn, u_id).await?;
+    let token = create_account_token(u_id, AccountTokenKind::PasswordReset, password_reset_token_hours(), conn).await?;
+    let url = format!("{}/reset_password?token={token}", frontend_url());
+
+    send_password_reset_mail(email, user_data, &url).await
+}
+
+/// Always answers with success and sends the mail in the background, so the response does not reveal
+/// whether the email is registered.
+#[utoipa::path(
+    post,
+    path = "/password/forgot"
+)]
+pub async fn forgot_password(
+    mut conn: DBConnection,
+    Json(forgot): Json<ForgotPassword>
+) -> APIResult<()> {
+    let Some(user) = get_user_with_email(&mut conn, &forgot.email).await else {
+        return Ok(())
+    };
+
+    tokio::spawn(async move {
+        if let Err(err) = send_reset_link(user.id, &user.email, &mut conn).await {
+            warn!("Could not send password reset mail to user {}: {err}", user.id);
+        }
+    });
+
+    Ok(())
+}
+
+/// Sets the new password. Changing the hash also ends all sessions of the user,
+/// because the session is bound to the password hash.
+#[utoipa::path(
+    post,
+    path = "/password/reset"
+)]
+pub async fn reset_password(
+    mut conn: DBConnection,
+    Json(reset): Json<ResetPassword>
+) -> APIResult<()> {
+    let u_id = consume_account_token(&reset.token, AccountTokenKind::PasswordReset, &mut conn).await?;
+    let pw_hash = hash_password(&reset.password)?;
+
+    // A reset after a compromise has to lock out whoever had access, so all logins and API tokens end here.
+    conn.transaction(|conn| async move {
+        diesel::update(users::table)
+            .filter(users::id.eq(u_id))
+            .set(users::pw_hash.eq(pw_hash))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        delete_api_tokens_of_user(u_id, conn).await?;
+        delete_sessions_of_user(u_id, conn).await
+    }.scope_boxed()).await
+}
+
+pub fn add_password_reset_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/password/forgot", post(forgot_password))
+        .route("/password/reset", post(reset_password))
+}
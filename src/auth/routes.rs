@@ -1,41 +1,38 @@
-use argon2::{Argon2, PasswordHasher};
-use argon2::password_hash::rand_core::OsRng;
-use argon2::password_hash::SaltString;
 use axum::{debug_handler, Json, Router};
-use axum::extract::Path;
+use axum::extract::{Path, State};
 use axum::routing::{get, post};
 use axum_login::UserId;
+use axum_login::tower_sessions::Session;
 use diesel::{QueryDsl, SelectableHelper};
 use diesel_async::RunQueryDsl;
+use scoped_futures::ScopedFutureExt;
 use diesel::ExpressionMethods;
-use crate::auth::{AuthSession, Credentials, get_user_with_email, NewUser, User};
+use crate::auth::{AuthSession, Credentials, get_user_with_email, hash_password, NewUser, User};
+use crate::auth::login_protection::{check_login_allowed, clear_login_failures, record_login_failure, ClientAddress};
+use crate::auth::two_factor::{delete_two_factor_of_user, has_two_factor, start_two_factor_login};
+use crate::auth::api_tokens::delete_api_tokens_of_user;
+use crate::auth::email_verification::{set_email_confirmed, spawn_verification_mail};
+use crate::auth::tokens::delete_account_tokens_of_user;
 use crate::auth::util::{auth_to_logged_in_id, auth_and_path_to_id_is_me_or_i_am_admin};
 use crate::backend::{Backend, DBConnection};
 use crate::error::{APIError, APIResult};
 use crate::events::slots::after_unregister;
 use crate::events::users::{EventUser};
+use crate::events::calendar::delete_calendar_token_of_user;
+use crate::events::guests::delete_guests_of_user;
+use crate::events::util::lock_event;
 use crate::firebase::{firebase_get_user_data, firebase_is_user_new, firebase_is_user_verified, firebase_login_user, insert_user_data_from_firebase};
-use crate::permissions::{is_admin, is_check_attended, is_verified, UserPermission};
+use crate::permissions::{delete_event_permissions_of_user, is_admin, is_check_attended, is_verified, UserPermission};
 use crate::permissions::routes::post_permission_add;
 use crate::schema::{event_user, permission, user_action, user_data, users};
 use crate::schema::users::{email, id};
 
-#[utoipa::path(
-    post,
-    path = "/signup"
-)]
-async fn sign_up(
-    mut conn: DBConnection,
-    Json(credentials): Json<Credentials>
-) -> APIResult<()> {
-
-    if get_user_with_email(&mut conn, &credentials.email).await.is_some() {
+async fn create_user(conn: &mut DBConnection, credentials: Credentials) -> APIResult<i32> {
+    if get_user_with_email(conn, &credentials.email).await.is_some() {
         return Err(APIError::EmailUsed);
     }
 
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    let pw_hash = argon2.hash_password(credentials.password.as_bytes(), &salt).unwrap().to_string();
+    let pw_hash = hash_password(&credentials.password)?;
 
     let new_user = NewUser{
         email: credentials.email,
@@ -44,9 +41,25 @@ async fn sign_up(
 
     diesel::insert_into(users::table)
         .values(new_user)
-        .execute(&mut conn.0)
+        .returning(users::id)
+        .get_result::<i32>(&mut conn.0)
         .await
-        .map_err(APIError::internal)?;
+        .map_err(APIError::internal)
+}
+
+/// New users start with an unconfirmed email and get a verification link.
+#[utoipa::path(
+    post,
+    path = "/signup"
+)]
+async fn sign_up(
+    mut conn: DBConnection,
+    Json(credentials): Json<Credentials>
+) -> APIResult<()> {
+    let user_email = credentials.email.clone();
+    let u_id = create_user(&mut conn, credentials).await?;
+
+    spawn_verification_mail(u_id, user_email, conn);
 
     Ok(())
 }
@@ -58,19 +71,28 @@ async fn sign_up(
 #[debug_handler]
 async fn login(
     mut auth_session: AuthSession,
+    session: Session,
+    ClientAddress(address): ClientAddress,
     Json(credentials): Json<Credentials>,
 ) -> APIResult<Json<UserId<Backend>>> {
-    
+    let mut conn = auth_session.backend.get_connection().await?;
+    check_login_allowed(&credentials.email, &address, &mut conn).await?;
+
     let user = match auth_session.authenticate(credentials.clone()).await {
         Ok(Some(user)) => user,
         Ok(None) => {
-            if let Ok(firebase_id) = firebase_login_user(credentials.clone()).await {
+            // Only unknown emails can be old accounts, so failed logins of known users never reach Firebase.
+            let known = get_user_with_email(&mut conn, &credentials.email).await.is_some();
+            let firebase_id = if known { None } else { firebase_login_user(credentials.clone()).await.ok() };
+
+            if let Some(firebase_id) = firebase_id {
                 let firebase_user_data = firebase_get_user_data(&firebase_id).await?;
                 let firebase_user_new = firebase_is_user_new(&firebase_id).await?;
                 let firebase_verified = firebase_is_user_verified(&firebase_id).await?;
 
-                let conn = auth_session.backend.get_connection().await?;
-                sign_up(conn, Json(credentials.clone())).await?;
+                // The email was already in use on the old page, so it counts as confirmed.
+                let u_id = create_user(&mut conn, credentials.clone()).await?;
+                set_email_confirmed(u_id, &mut conn).await?;
                 let user = auth_session.authenticate(credentials.clone()).await
                     .map_err(APIError::internal)?;
                 if user.is_none() {
@@ -87,12 +109,21 @@ async fn login(
 
                 user
             } else {
+                record_login_failure(&credentials.email, &address, &mut conn).await?;
                 return Err(APIError::InvalidCredentials);
             }
         },
         Err(err) => return Err(APIError::internal(err)),
     };
 
+    if has_two_factor(user.id, &mut conn).await {
+        start_two_factor_login(&session, user.id).await?;
+        return Err(APIError::SecondFactorRequired);
+    }
+
+    clear_login_failures(&credentials.email, &mut conn).await?;
+    drop(conn);
+
     if let Err(err) = auth_session.login(&user).await {
         return Err(APIError::internal(err));
     }
@@ -166,44 +197,73 @@ async fn get_all_users(DBConnection(mut conn): DBConnection) -> crate::error::AP
     path = "/user/{id}/remove"
 )]
 async fn remove_user(
+    State(backend): State<Backend>,
     mut conn: DBConnection,
     Path(u_id): Path<i32>
 ) -> APIResult<()> {
-    diesel::delete(user_action::table)
-        .filter(user_action::user_id.eq(u_id))
-        .execute(&mut conn.0)
-        .await
-        .map_err(APIError::internal)?;
+    let event_ids = conn.transaction(|conn| async move {
+        let mut event_ids = event_user::table
+            .filter(event_user::user_id.eq(u_id))
+            .select(event_user::event_id)
+            .get_results::<i32>(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
 
-    let removed_event_users = diesel::delete(event_user::table)
-        .filter(event_user::user_id.eq(u_id))
-        .returning(EventUser::as_select())
-        .get_results::<EventUser>(&mut conn.0)
-        .await
-        .map_err(APIError::internal)?;
+        // Lock in a fixed order so parallel removals can not deadlock.
+        event_ids.sort();
+        for e_id in event_ids.iter().copied() {
+            lock_event(e_id, conn).await?;
+        }
 
-    for removed_event_user in removed_event_users {
-        after_unregister(removed_event_user, &mut conn).await?
-    }
+        delete_guests_of_user(u_id, conn).await?;
 
-    diesel::delete(permission::table)
-        .filter(permission::user_id.eq(u_id))
-        .execute(&mut conn.0)
-        .await
-        .map_err(APIError::internal)?;
+        diesel::delete(user_action::table)
+            .filter(user_action::user_id.eq(u_id))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
 
-    diesel::delete(user_data::table)
-        .filter(user_data::user_id.eq(u_id))
-        .execute(&mut conn.0)
-        .await
-        .map_err(APIError::internal)?;
+        let removed_event_users = diesel::delete(event_user::table)
+            .filter(event_user::user_id.eq(u_id))
+            .returning(EventUser::as_select())
+            .get_results::<EventUser>(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
 
-    diesel::delete(users::table)
-        .filter(users::id.eq(u_id))
-        .execute(&mut conn.0)
-        .await
-        .map_err(APIError::internal)?;
+        for removed_event_user in removed_event_users {
+            after_unregister(removed_event_user, conn).await?
+        }
+
+        delete_calendar_token_of_user(u_id, conn).await?;
+        delete_account_tokens_of_user(u_id, conn).await?;
+        delete_two_factor_of_user(u_id, conn).await?;
+        delete_api_tokens_of_user(u_id, conn).await?;
+        delete_event_permissions_of_user(conn, u_id).await?;
 
+        diesel::delete(permission::table)
+            .filter(permission::user_id.eq(u_id))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        diesel::delete(user_data::table)
+            .filter(user_data::user_id.eq(u_id))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        diesel::delete(users::table)
+            .filter(users::id.eq(u_id))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        Ok(event_ids)
+    }.scope_boxed()).await?;
+
+    for e_id in event_ids {
+        backend.live.notify(e_id);
+    }
     Ok(())
 }
 
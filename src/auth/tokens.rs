@@ -0,0 +1,83 @@
+use chrono::{Duration, Local};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use sha2::{Digest, Sha256};
+use crate::auth::util::generate_token;
+use crate::backend::DBConnection;
+use crate::error::{APIError, APIResult};
+use crate::schema::account_token;
+
+/// What a single-use account token is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(diesel_derive_enum::DbEnum)]
+#[ExistingTypePath = "crate::schema::sql_types::Accounttokenkind"]
+pub enum AccountTokenKind {
+    PasswordReset,
+    EmailVerification,
+    EmailChange,
+}
+
+/// Only the hash of a token is stored, so a leaked database does not leak usable links.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Creates a token that expires after the given hours and replaces older tokens of the same kind.
+pub async fn create_account_token(u_id: i32, kind: AccountTokenKind, hours: i64, conn: &mut DBConnection) -> APIResult<String> {
+    create_account_token_with_data(u_id, kind, hours, None, conn).await
+}
+
+/// Like `create_account_token`, but stores data the action needs later, e.g. the new email.
+pub async fn create_account_token_with_data(u_id: i32, kind: AccountTokenKind, hours: i64, data: Option<String>, conn: &mut DBConnection) -> APIResult<String> {
+    let token = generate_token();
+
+    diesel::delete(account_token::table)
+        .filter(account_token::user_id.eq(u_id))
+        .filter(account_token::kind.eq(kind))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    diesel::insert_into(account_token::table)
+        .values((
+            account_token::user_id.eq(u_id),
+            account_token::kind.eq(kind),
+            account_token::token_hash.eq(hash_token(&token)),
+            account_token::expires.eq(Local::now().naive_local() + Duration::hours(hours)),
+            account_token::data.eq(data),
+        ))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(token)
+}
+
+/// Deletes the token and returns its user. Fails if the token is unknown, of another kind or expired.
+pub async fn consume_account_token(token: &str, kind: AccountTokenKind, conn: &mut DBConnection) -> APIResult<i32> {
+    let (u_id, _) = consume_account_token_with_data(token, kind, conn).await?;
+    Ok(u_id)
+}
+
+/// Like `consume_account_token`, but also returns the data stored with the token.
+pub async fn consume_account_token_with_data(token: &str, kind: AccountTokenKind, conn: &mut DBConnection) -> APIResult<(i32, Option<String>)> {
+    diesel::delete(account_token::table)
+        .filter(account_token::token_hash.eq(hash_token(token)))
+        .filter(account_token::kind.eq(kind))
+        .filter(account_token::expires.gt(Local::now().naive_local()))
+        .returning((account_token::user_id, account_token::data))
+        .get_result::<(i32, Option<String>)>(&mut conn.0)
+        .await
+        .optional()
+        .map_err(APIError::internal)?
+        .ok_or(APIError::InvalidToken)
+}
+
+pub async fn delete_account_tokens_of_user(u_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    diesel::delete(account_token::table)
+        .filter(account_token::user_id.eq(u_id))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    Ok(())
+}
@@ -0,0 +1,399 @@
+use axum::extract::Path;
+use axum::{Json, Router};
+use axum::routing::{get, post};
+use axum_login::AuthnBackend;
+use axum_login::tower_sessions::Session;
+use chrono::{Duration, Local, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use totp_rs::{Algorithm, TOTP};
+use utoipa::ToSchema;
+use crate::auth::{AuthSession, User};
+use crate::auth::account::auth_to_user_with_password;
+use crate::auth::login_protection::{check_login_allowed, clear_login_failures, record_login_failure, ClientAddress};
+use crate::auth::tokens::hash_token;
+use crate::auth::util::generate_token;
+use crate::backend::{Backend, DBConnection};
+use crate::config::{totp_issuer, two_factor_login_minutes, two_factor_required_for_staff};
+use crate::error::{APIError, APIResult};
+use crate::schema::{totp_recovery_code, user_totp};
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const PENDING_LOGIN_KEY: &str = "two_factor_pending";
+
+/// A user that entered the right password but not yet the second factor.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PendingLogin {
+    user_id: i32,
+    expires: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub required: bool,
+}
+
+/// The secret in base32 for manual entry and as `otpauth://` URI for QR codes.
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct TwoFactorEnrollment {
+    pub secret: String,
+    pub uri: String,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct TwoFactorCode {
+    pub code: String,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct TwoFactorPassword {
+    pub password: String,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct DisableTwoFactor {
+    pub password: String,
+    pub code: String,
+}
+
+fn auth_to_user(auth: &AuthSession) -> APIResult<User> {
+    auth.user.clone().ok_or(APIError::UNAUTHORIZED)
+}
+
+fn build_totp(secret: &str, email: &str) -> APIResult<TOTP> {
+    let secret = hex::decode(secret).map_err(APIError::internal)?;
+    TOTP::new(Algorithm::SHA1, 6, 1, 30, secret, Some(totp_issuer()), email.to_string())
+        .map_err(APIError::internal)
+}
+
+/// Returns the secret, whether it is enabled and the last used time step.
+async fn get_user_totp(u_id: i32, conn: &mut DBConnection) -> APIResult<Option<(String, bool, Option<i64>)>> {
+    user_totp::table
+        .filter(user_totp::user_id.eq(u_id))
+        .select((user_totp::secret, user_totp::enabled, user_totp::last_step))
+        .get_result::<(String, bool, Option<i64>)>(&mut conn.0)
+        .await
+        .optional()
+        .map_err(APIError::internal)
+}
+
+pub async fn has_two_factor(u_id: i32, conn: &mut DBConnection) -> bool {
+    user_totp::table
+        .filter(user_totp::user_id.eq(u_id))
+        .filter(user_totp::enabled.eq(true))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .is_ok_and(|count| count > 0)
+}
+
+/// Checks a code of the authenticator app. Every time step is only accepted once,
+/// so an observed code can not be used again.
+async fn verify_totp_code(u_id: i32, email: &str, code: &str, conn: &mut DBConnection) -> APIResult<bool> {
+    let Some((secret, _, last_step)) = get_user_totp(u_id, conn).await? else {
+        return Ok(false)
+    };
+    let totp = build_totp(&secret, email)?;
+
+    let current_step = Utc::now().timestamp() as u64 / totp.step;
+    let skew = totp.skew as u64;
+    for step in current_step.saturating_sub(skew)..=current_step + skew {
+        if last_step.is_some_and(|last_step| step as i64 <= last_step) {
+            continue
+        }
+        if totp.generate(step * totp.step) != code.trim() {
+            continue
+        }
+
+        diesel::update(user_totp::table)
+            .filter(user_totp::user_id.eq(u_id))
+            .set(user_totp::last_step.eq(step as i64))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+        return Ok(true)
+    }
+
+    Ok(false)
+}
+
+/// Recovery codes can only be used once.
+async fn use_recovery_code(u_id: i32, code: &str, conn: &mut DBConnection) -> APIResult<bool> {
+    let used = diesel::delete(totp_recovery_code::table)
+        .filter(totp_recovery_code::user_id.eq(u_id))
+        .filter(totp_recovery_code::code_hash.eq(hash_token(code.trim())))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(used > 0)
+}
+
+/// Accepts a code of the authenticator app or a recovery code.
+pub async fn verify_second_factor(user: &User, code: &str, conn: &mut DBConnection) -> APIResult<bool> {
+    if verify_totp_code(user.id, &user.email, code, conn).await? {
+        return Ok(true)
+    }
+    use_recovery_code(user.id, code, conn).await
+}
+
+/// Replaces all recovery codes of the user. Only the hashes are stored, so the codes are only shown once.
+async fn generate_recovery_codes(u_id: i32, conn: &mut DBConnection) -> APIResult<Vec<String>> {
+    diesel::delete(totp_recovery_code::table)
+        .filter(totp_recovery_code::user_id.eq(u_id))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let mut codes = vec![];
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let code = generate_token()[..12].to_string();
+
+        diesel::insert_into(totp_recovery_code::table)
+            .values((
+                totp_recovery_code::user_id.eq(u_id),
+                totp_recovery_code::code_hash.eq(hash_token(&code)),
+            ))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        codes.push(code);
+    }
+
+    Ok(codes)
+}
+
+pub async fn delete_two_factor_of_user(u_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    diesel::delete(totp_recovery_code::table)
+        .filter(totp_recovery_code::user_id.eq(u_id))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    diesel::delete(user_totp::table)
+        .filter(user_totp::user_id.eq(u_id))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(())
+}
+
+/// Remembers in the session that the password was correct. The user is only logged in after `/login/two_factor`.
+pub async fn start_two_factor_login(session: &Session, u_id: i32) -> APIResult<()> {
+    let pending = PendingLogin {
+        user_id: u_id,
+        expires: (Local::now() + Duration::minutes(two_factor_login_minutes())).timestamp(),
+    };
+
+    session.insert(PENDING_LOGIN_KEY, pending)
+        .await
+        .map_err(APIError::internal)
+}
+
+#[utoipa::path(
+    post,
+    path = "/login/two_factor"
+)]
+pub async fn login_two_factor(
+    mut auth: AuthSession,
+    session: Session,
+    ClientAddress(address): ClientAddress,
+    Json(code): Json<TwoFactorCode>,
+) -> APIResult<Json<i32>> {
+    let pending = session.get::<PendingLogin>(PENDING_LOGIN_KEY)
+        .await
+        .map_err(APIError::internal)?
+        .filter(|pending| pending.expires > Local::now().timestamp())
+        .ok_or(APIError::UNAUTHORIZED)?;
+
+    let user = auth.backend.get_user(&pending.user_id)
+        .await?
+        .ok_or(APIError::UNAUTHORIZED)?;
+
+    let mut conn = auth.backend.get_connection().await?;
+    check_login_allowed(&user.email, &address, &mut conn).await?;
+
+    if !verify_second_factor(&user, &code.code, &mut conn).await? {
+        record_login_failure(&user.email, &address, &mut conn).await?;
+        return Err(APIError::InvalidTwoFactorCode)
+    }
+
+    clear_login_failures(&user.email, &mut conn).await?;
+    drop(conn);
+
+    session.remove::<PendingLogin>(PENDING_LOGIN_KEY)
+        .await
+        .map_err(APIError::internal)?;
+    auth.login(&user)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(Json(user.id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/two_factor"
+)]
+pub async fn get_two_factor_status(
+    auth: AuthSession,
+) -> APIResult<Json<TwoFactorStatus>> {
+    let user = auth_to_user(&auth)?;
+    let mut conn = auth.backend.get_connection().await?;
+
+    Ok(Json(TwoFactorStatus {
+        enabled: has_two_factor(user.id, &mut conn).await,
+        required: two_factor_required_for_staff(),
+    }))
+}
+
+/// Creates a new secret. It is only used for login once a code was confirmed with `/user/two_factor/enable`.
+#[utoipa::path(
+    post,
+    path = "/user/two_factor/enroll"
+)]
+pub async fn enroll_two_factor(
+    auth: AuthSession,
+    Json(password): Json<TwoFactorPassword>,
+) -> APIResult<Json<TwoFactorEnrollment>> {
+    let user = auth_to_user_with_password(&auth, &password.password)?;
+    let mut conn = auth.backend.get_connection().await?;
+
+    if has_two_factor(user.id, &mut conn).await {
+        return Err(APIError::TwoFactorAlreadyEnabled)
+    }
+
+    let mut secret = [0u8; 20];
+    OsRng.fill_bytes(&mut secret);
+    let secret = hex::encode(secret);
+    let totp = build_totp(&secret, &user.email)?;
+
+    diesel::insert_into(user_totp::table)
+        .values((
+            user_totp::user_id.eq(user.id),
+            user_totp::secret.eq(&secret),
+            user_totp::enabled.eq(false),
+        ))
+        .on_conflict(user_totp::user_id)
+        .do_update()
+        .set((
+            user_totp::secret.eq(&secret),
+            user_totp::last_step.eq(None::<i64>),
+        ))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(Json(TwoFactorEnrollment {
+        secret: totp.get_secret_base32(),
+        uri: totp.get_url(),
+    }))
+}
+
+/// Enables two-factor authentication and returns the recovery codes.
+#[utoipa::path(
+    post,
+    path = "/user/two_factor/enable"
+)]
+pub async fn enable_two_factor(
+    auth: AuthSession,
+    Json(code): Json<TwoFactorCode>,
+) -> APIResult<Json<Vec<String>>> {
+    let user = auth_to_user(&auth)?;
+    let mut conn = auth.backend.get_connection().await?;
+
+    let Some((_, enabled, _)) = get_user_totp(user.id, &mut conn).await? else {
+        return Err(APIError::TwoFactorNotEnrolled)
+    };
+    if enabled {
+        return Err(APIError::TwoFactorAlreadyEnabled)
+    }
+
+    if !verify_totp_code(user.id, &user.email, &code.code, &mut conn).await? {
+        return Err(APIError::InvalidTwoFactorCode)
+    }
+
+    diesel::update(user_totp::table)
+        .filter(user_totp::user_id.eq(user.id))
+        .set(user_totp::enabled.eq(true))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let codes = generate_recovery_codes(user.id, &mut conn).await?;
+    Ok(Json(codes))
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/two_factor/disable"
+)]
+pub async fn disable_two_factor(
+    auth: AuthSession,
+    Json(disable): Json<DisableTwoFactor>,
+) -> APIResult<()> {
+    let user = auth_to_user_with_password(&auth, &disable.password)?;
+    let mut conn = auth.backend.get_connection().await?;
+
+    if !has_two_factor(user.id, &mut conn).await {
+        return Err(APIError::TwoFactorNotEnrolled)
+    }
+    if !verify_second_factor(&user, &disable.code, &mut conn).await? {
+        return Err(APIError::InvalidTwoFactorCode)
+    }
+
+    delete_two_factor_of_user(user.id, &mut conn).await
+}
+
+/// Replaces the recovery codes, e.g. after some of them were used.
+#[utoipa::path(
+    post,
+    path = "/user/two_factor/recovery_codes"
+)]
+pub async fn regenerate_recovery_codes(
+    auth: AuthSession,
+    Json(code): Json<TwoFactorCode>,
+) -> APIResult<Json<Vec<String>>> {
+    let user = auth_to_user(&auth)?;
+    let mut conn = auth.backend.get_connection().await?;
+
+    if !has_two_factor(user.id, &mut conn).await {
+        return Err(APIError::TwoFactorNotEnrolled)
+    }
+    if !verify_totp_code(user.id, &user.email, &code.code, &mut conn).await? {
+        return Err(APIError::InvalidTwoFactorCode)
+    }
+
+    let codes = generate_recovery_codes(user.id, &mut conn).await?;
+    Ok(Json(codes))
+}
+
+/// For users that lost their authenticator and their recovery codes.
+#[utoipa::path(
+    post,
+    path = "/user/{id}/two_factor/reset"
+)]
+pub async fn reset_two_factor(
+    mut conn: DBConnection,
+    Path(u_id): Path<i32>,
+) -> APIResult<()> {
+    delete_two_factor_of_user(u_id, &mut conn).await
+}
+
+pub fn add_two_factor_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/login/two_factor", post(login_two_factor))
+        .route("/user/two_factor", get(get_two_factor_status))
+        .route("/user/two_factor/enroll", post(enroll_two_factor))
+        .route("/user/two_factor/enable", post(enable_two_factor))
+        .route("/user/two_factor/disable", post(disable_two_factor))
+        .route("/user/two_factor/recovery_codes", post(regenerate_recovery_codes))
+}
+
+pub fn add_admin_two_factor_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/user/:id/two_factor/reset", post(reset_two_factor))
+}
@@ -1,8 +1,9 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
 use axum_login::AuthzBackend;
 use crate::auth::{AuthSession};
 use crate::backend::{DBConnection};
 use crate::error::APIError;
-use crate::permissions::{has_permission, is_admin, Permission, UserPermission};
+use crate::permissions::{has_event_permission, has_permission, is_admin, EventPermissionType, Permission, UserPermission};
 use crate::error::APIResult;
 
 pub async fn auth_to_conn_expect_logged_in(
@@ -119,6 +120,23 @@ pub async fn auth_to_id_is_me_or_i_am_admin(
     Ok(conn)
 }
 
+pub async fn auth_to_id_is_me_or_i_am_admin_check_is_admin(
+    auth_session: AuthSession,
+    id: i32,
+) -> APIResult<(bool, DBConnection)> {
+    if auth_session.user.is_none() {
+        return Err(APIError::UNAUTHORIZED);
+    }
+    let user = auth_session.user.unwrap();
+
+    let mut conn = auth_session.backend.get_connection().await?;
+    let admin = is_admin(&mut conn, user.id).await;
+    if user.id != id && !admin {
+        return Err(APIError::UNAUTHORIZED);
+    }
+
+    Ok((admin, conn))
+}
 
 pub async fn auth_and_path_to_id_is_me_or_i_am_admin(
     auth_session: AuthSession,
@@ -139,15 +157,61 @@ pub async fn auth_and_path_to_id_is_me_or_i_am_admin(
 
 pub async fn auth_to_conn_expect_logged_in_and_check_attended(
     auth_session: AuthSession,
+    e_id: i32,
+) -> APIResult<DBConnection> {
+    if auth_session.user.is_none() {
+        return Err(APIError::UNAUTHORIZED);
+    }
+    let user = auth_session.user.unwrap();
+
+    let mut conn = auth_session.backend.get_connection().await?;
+    if !has_permission(&mut conn, user.id, UserPermission::CheckAttended).await
+        && !has_event_permission(&mut conn, user.id, e_id, EventPermissionType::CheckAttended).await {
+        return Err(APIError::UNAUTHORIZED);
+    }
+
+    Ok(conn)
+}
+
+pub async fn auth_to_conn_expect_admin_or_organizer(
+    auth_session: AuthSession,
+    e_id: i32,
 ) -> APIResult<DBConnection> {
     if auth_session.user.is_none() {
         return Err(APIError::UNAUTHORIZED);
     }
+    let user = auth_session.user.unwrap();
 
     let mut conn = auth_session.backend.get_connection().await?;
-    if !has_permission(&mut conn, auth_session.user.unwrap().id, UserPermission::CheckAttended).await {
+    if !is_admin(&mut conn, user.id).await
+        && !has_event_permission(&mut conn, user.id, e_id, EventPermissionType::Organizer).await {
         return Err(APIError::UNAUTHORIZED);
     }
 
     Ok(conn)
-}
\ No newline at end of file
+}
+
+pub async fn auth_to_conn_expect_admin_or_organizer_check_is_admin(
+    auth_session: AuthSession,
+    e_id: i32,
+) -> APIResult<(bool, DBConnection)> {
+    if auth_session.user.is_none() {
+        return Err(APIError::UNAUTHORIZED);
+    }
+    let user = auth_session.user.unwrap();
+
+    let mut conn = auth_session.backend.get_connection().await?;
+    let admin = is_admin(&mut conn, user.id).await;
+    if !admin && !has_event_permission(&mut conn, user.id, e_id, EventPermissionType::Organizer).await {
+        return Err(APIError::UNAUTHORIZED);
+    }
+
+    Ok((admin, conn))
+}
+
+/// Random 32 byte token in hex, used for links that authenticate without a session.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -1,15 +1,18 @@
 use async_trait::async_trait;
 use axum::extract::{FromRef, FromRequestParts};
-use diesel_async::AsyncPgConnection;
+use diesel_async::{AnsiTransactionManager, AsyncPgConnection, TransactionManager};
 use diesel_async::pooled_connection::AsyncDieselConnectionManager;
 use http::request::Parts;
+use scoped_futures::ScopedBoxFuture;
 use crate::error::{APIError, APIResult};
+use crate::events::live::LiveUpdates;
 
 pub type DBPool = bb8::Pool<AsyncDieselConnectionManager<AsyncPgConnection>>;
 
 #[derive(Clone)]
 pub struct Backend {
-    pub db_pool: DBPool
+    pub db_pool: DBPool,
+    pub live: LiveUpdates,
 }
 
 pub struct DBConnection (
@@ -28,6 +31,7 @@ impl Backend {
 
         Ok(Backend {
             db_pool: pool,
+            live: LiveUpdates::new(),
         })
     }
 
@@ -40,6 +44,34 @@ impl Backend {
     }
 }
 
+impl DBConnection {
+    /// Runs the callback inside a database transaction. The transaction is rolled back if the callback fails.
+    pub async fn transaction<'a, R, F>(&'a mut self, callback: F) -> APIResult<R>
+        where
+            F: for<'r> FnOnce(&'r mut DBConnection) -> ScopedBoxFuture<'a, 'r, APIResult<R>> + Send + 'a,
+            R: Send + 'a,
+    {
+        AnsiTransactionManager::begin_transaction(&mut *self.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        match callback(&mut *self).await {
+            Ok(value) => {
+                AnsiTransactionManager::commit_transaction(&mut *self.0)
+                    .await
+                    .map_err(APIError::internal)?;
+                Ok(value)
+            }
+            Err(err) => {
+                AnsiTransactionManager::rollback_transaction(&mut *self.0)
+                    .await
+                    .map_err(APIError::internal)?;
+                Err(err)
+            }
+        }
+    }
+}
+
 #[async_trait]
 impl<S> FromRequestParts<S> for DBConnection
     where
@@ -0,0 +1,185 @@
+use std::str::FromStr;
+use axum_login::tower_sessions::cookie::SameSite;
+use crate::error::{APIError, APIResult};
+
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Seconds between two runs of the event scheduler.
+pub fn scheduler_interval_secs() -> u64 {
+    env_or("SCHEDULER_INTERVAL_SECS", 60)
+}
+
+/// Days of inactivity after which a session expires.
+pub fn session_expiry_days() -> i64 {
+    env_or("SESSION_EXPIRY_DAYS", 30)
+}
+
+/// Seconds between two deletions of expired sessions.
+pub fn session_cleanup_interval_secs() -> u64 {
+    env_or("SESSION_CLEANUP_INTERVAL_SECS", 3600)
+}
+
+pub fn session_cookie_name() -> String {
+    env_or("SESSION_COOKIE_NAME", "id".to_string())
+}
+
+/// Only disable for local development without https.
+pub fn session_secure() -> bool {
+    env_or("SESSION_SECURE", true)
+}
+
+/// One of `strict`, `lax` or `none`.
+pub fn session_same_site() -> SameSite {
+    match std::env::var("SESSION_SAME_SITE").as_deref() {
+        Ok("lax") => SameSite::Lax,
+        Ok("none") => SameSite::None,
+        _ => SameSite::Strict,
+    }
+}
+
+/// Only failed logins of the last minutes count for backoff and lockout.
+pub fn login_failure_window_minutes() -> i64 {
+    env_or("LOGIN_FAILURE_WINDOW_MINUTES", 60)
+}
+
+/// Failed logins per email before every further attempt has to wait.
+pub fn login_free_attempts() -> i64 {
+    env_or("LOGIN_FREE_ATTEMPTS", 3)
+}
+
+/// Failed logins per client address before every further attempt has to wait.
+/// Higher than per email, because many users can share one address.
+pub fn login_address_free_attempts() -> i64 {
+    env_or("LOGIN_ADDRESS_FREE_ATTEMPTS", 10)
+}
+
+/// The wait after the free attempts starts here and doubles with every failure.
+pub fn login_backoff_base_secs() -> i64 {
+    env_or("LOGIN_BACKOFF_BASE_SECS", 2)
+}
+
+pub fn login_backoff_max_secs() -> i64 {
+    env_or("LOGIN_BACKOFF_MAX_SECS", 900)
+}
+
+/// Failed logins per email after which the account is locked.
+pub fn login_lockout_threshold() -> i64 {
+    env_or("LOGIN_LOCKOUT_THRESHOLD", 10)
+}
+
+pub fn login_lockout_minutes() -> i64 {
+    env_or("LOGIN_LOCKOUT_MINUTES", 30)
+}
+
+/// Take the client address from the `X-Forwarded-For` header. The backend only listens on localhost behind the
+/// reverse proxy, without the header every login would come from the proxy address and share one backoff.
+/// Only disable when the backend is reachable without a proxy that sets the header.
+pub fn trust_forwarded_for() -> bool {
+    env_or("TRUST_FORWARDED_FOR", true)
+}
+
+/// Admin and check-attended permissions only take effect for users with two-factor authentication.
+pub fn two_factor_required_for_staff() -> bool {
+    env_or("TWO_FACTOR_REQUIRED_FOR_STAFF", false)
+}
+
+/// Shown as account issuer in authenticator apps.
+pub fn totp_issuer() -> String {
+    env_or("TOTP_ISSUER", "RopeLab".to_string())
+}
+
+/// Minutes a user has to enter the second factor after the password.
+pub fn two_factor_login_minutes() -> i64 {
+    env_or("TWO_FACTOR_LOGIN_MINUTES", 5)
+}
+
+/// Key for signing check-in tokens. Changing it invalidates all issued tokens.
+pub fn check_in_secret() -> APIResult<String> {
+    std::env::var("CHECK_IN_SECRET").map_err(|_| APIError::internal("CHECK_IN_SECRET is not set"))
+}
+
+/// Base url of the frontend, used for links in mails.
+pub fn frontend_url() -> String {
+    env_or("FRONTEND_URL", "https://ropelab.de".to_string())
+}
+
+/// Hours until a password reset link expires.
+pub fn password_reset_token_hours() -> i64 {
+    env_or("PASSWORD_RESET_TOKEN_HOURS", 2)
+}
+
+/// Hours until an email verification link expires.
+pub fn email_verification_token_hours() -> i64 {
+    env_or("EMAIL_VERIFICATION_TOKEN_HOURS", 48)
+}
+
+/// Hours until the confirmation link for a new email expires.
+pub fn email_change_token_hours() -> i64 {
+    env_or("EMAIL_CHANGE_TOKEN_HOURS", 24)
+}
+
+/// Length of an event in calendar feeds, the event table only stores the start.
+pub fn calendar_event_duration_hours() -> i64 {
+    env_or("CALENDAR_EVENT_DURATION_HOURS", 3)
+}
+
+/// Attended events after which a user stops being new, 0 disables the graduation.
+pub fn new_member_graduation_attendances() -> i64 {
+    env_or("NEW_MEMBER_GRADUATION_ATTENDANCES", 3)
+}
+
+/// Past events of the last days are used to estimate the admission chance of a waiting position.
+pub fn admission_history_days() -> i64 {
+    env_or("ADMISSION_HISTORY_DAYS", 365)
+}
+
+/// What happens to users that reached the incident threshold of their reliability record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReliabilityPolicy {
+    Off,
+    /// The user never gets a free slot directly and is always put on the waiting list.
+    WaitingList,
+    /// The user can not register until the cooling-off period after the last incident is over.
+    CoolingOff,
+}
+
+impl FromStr for ReliabilityPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(ReliabilityPolicy::Off),
+            "waiting_list" => Ok(ReliabilityPolicy::WaitingList),
+            "cooling_off" => Ok(ReliabilityPolicy::CoolingOff),
+            _ => Err(()),
+        }
+    }
+}
+
+pub fn reliability_policy() -> ReliabilityPolicy {
+    env_or("RELIABILITY_POLICY", ReliabilityPolicy::Off)
+}
+
+/// Unregistering less than this many hours before the event counts as late cancellation.
+pub fn late_cancel_hours() -> i64 {
+    env_or("LATE_CANCEL_HOURS", 24)
+}
+
+/// Only incidents of the last days count for the reliability record.
+pub fn reliability_window_days() -> i64 {
+    env_or("RELIABILITY_WINDOW_DAYS", 180)
+}
+
+/// Number of incidents from which the reliability policy applies.
+pub fn reliability_incident_threshold() -> i64 {
+    env_or("RELIABILITY_INCIDENT_THRESHOLD", 3)
+}
+
+pub fn reliability_cooling_off_days() -> i64 {
+    env_or("RELIABILITY_COOLING_OFF_DAYS", 30)
+}
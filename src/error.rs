@@ -37,6 +37,30 @@ pub enum APIError {
     #[message("Event ids dont match")]
     EventIdsDontMatch,
 
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("Series ids dont match")]
+    SeriesIdsDontMatch,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("The interval of the series has to be at least one day")]
+    InvalidSeries,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("An occurrence has to become visible before its register deadline and be archived after it")]
+    InvalidSeriesOffsets,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("Event is not part of the series")]
+    EventNotInSeries,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("Event already has participants")]
+    EventHasParticipants,
+
+    #[status_code(FORBIDDEN)]
+    #[message("Event is cancelled")]
+    EventCancelled,
+
     #[status_code(NOT_ACCEPTABLE)]
     #[message("The User is already registered to the event")]
     UserAlreadyRegistered,
@@ -48,6 +72,113 @@ pub enum APIError {
     #[status_code(FORBIDDEN)]
     #[message("Change guest not possible")]
     ChangeGuestsDenied,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("Guest not found")]
+    GuestNotFound,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("The user linked to the guest does not exist")]
+    GuestUserNotFound,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("Users can not bring themselves as guest")]
+    GuestIsSelf,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("The user linked to the guest is already registered for the event")]
+    GuestAlreadyRegistered,
+
+    #[status_code(FORBIDDEN)]
+    #[message("Registration for the event is closed")]
+    RegistrationClosed,
+
+    #[status_code(FORBIDDEN)]
+    #[message("Event is not visible")]
+    EventNotVisible,
+
+    #[status_code(FORBIDDEN)]
+    #[message("User is rejected from the event")]
+    UserRejected,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("User is not rejected from the event")]
+    UserNotRejected,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("User has no offer for the event")]
+    NoOffer,
+
+    #[status_code(FORBIDDEN)]
+    #[message("The offer has expired")]
+    OfferExpired,
+
+    #[status_code(FORBIDDEN)]
+    #[message("Invalid check-in token")]
+    InvalidCheckInToken,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("Check-in token is for a different event")]
+    CheckInWrongEvent,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("User is not admitted to the event")]
+    UserNotAdmitted,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("User is already checked in")]
+    AlreadyCheckedIn,
+
+    #[status_code(FORBIDDEN)]
+    #[message("Registration is blocked because of missed events")]
+    RegistrationBlocked,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("Event has no lottery")]
+    NoLottery,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("The lottery was already drawn")]
+    LotteryAlreadyDrawn,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("User is not on the waiting list")]
+    UserNotWaiting,
+
+    #[status_code(FORBIDDEN)]
+    #[message("Invalid or expired token")]
+    InvalidToken,
+
+    #[status_code(FORBIDDEN)]
+    #[message("Email is not confirmed")]
+    EmailNotConfirmed,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("Email is already confirmed")]
+    EmailAlreadyConfirmed,
+
+    #[status_code(TOO_MANY_REQUESTS)]
+    TooManyAttempts(#[key("retry_after")] i64),
+
+    #[status_code(UNAUTHORIZED)]
+    #[message("Second factor required")]
+    SecondFactorRequired,
+
+    #[status_code(FORBIDDEN)]
+    #[message("Invalid two-factor code")]
+    InvalidTwoFactorCode,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("Two-factor authentication is not set up")]
+    TwoFactorNotEnrolled,
+
+    #[status_code(NOT_ACCEPTABLE)]
+    #[message("Two-factor authentication is already enabled")]
+    TwoFactorAlreadyEnabled,
+
+    #[status_code(NOT_FOUND)]
+    #[message("API token not found")]
+    ApiTokenNotFound,
 }
 
 
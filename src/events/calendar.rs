@@ -0,0 +1,214 @@
+use axum::extract::Path;
+use axum::{Json, Router};
+use axum::routing::{get, post};
+use chrono::{Duration, Local, NaiveDateTime};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use http::HeaderName;
+use crate::auth::AuthSession;
+use crate::auth::tokens::hash_token;
+use crate::auth::util::{auth_to_id_is_me_or_i_am_admin, generate_token};
+use crate::backend::{Backend, DBConnection};
+use crate::config::calendar_event_duration_hours;
+use crate::error::{APIError, APIResult};
+use crate::events::users::EventUserState;
+use crate::markdown_files::get_event_user_state_text;
+use crate::schema::{calendar_token, event, event_user};
+
+const CALENDAR_NAME: &str = "RopeLab";
+const ICS_DATE_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+type CalendarResponse = ([(HeaderName, &'static str); 2], String);
+
+struct CalendarEntry {
+    id: i32,
+    date: NaiveDateTime,
+    cancelled: bool,
+    summary: String,
+}
+
+/// Escapes text values as required by RFC 5545.
+fn escape_ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Builds the calendar by hand. Dates are written as floating local time, like they are stored.
+fn build_calendar(entries: Vec<CalendarEntry>) -> String {
+    let stamp = Local::now().naive_local().format(ICS_DATE_FORMAT);
+    let duration = Duration::hours(calendar_event_duration_hours());
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//RopeLab//Backend//DE".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{CALENDAR_NAME}"),
+    ];
+
+    for entry in entries {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:event-{}@ropelab", entry.id));
+        lines.push(format!("DTSTAMP:{stamp}"));
+        lines.push(format!("DTSTART:{}", entry.date.format(ICS_DATE_FORMAT)));
+        lines.push(format!("DTEND:{}", (entry.date + duration).format(ICS_DATE_FORMAT)));
+        lines.push(format!("SUMMARY:{}", escape_ics_text(&entry.summary)));
+        lines.push(format!("STATUS:{}", if entry.cancelled { "CANCELLED" } else { "CONFIRMED" }));
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut calendar = lines.join("\r\n");
+    calendar.push_str("\r\n");
+    calendar
+}
+
+fn calendar_response(calendar: String) -> CalendarResponse {
+    ([(CONTENT_TYPE, "text/calendar; charset=utf-8"), (CACHE_CONTROL, "no-cache")], calendar)
+}
+
+async fn get_user_id_of_calendar_token(token: &str, conn: &mut DBConnection) -> APIResult<i32> {
+    calendar_token::table
+        .filter(calendar_token::token_hash.eq(hash_token(token)))
+        .select(calendar_token::user_id)
+        .get_result::<i32>(&mut conn.0)
+        .await
+        .optional()
+        .map_err(APIError::internal)?
+        .ok_or(APIError::UNAUTHORIZED)
+}
+
+/// Only the hash of the token is stored, the returned token can not be looked up again.
+async fn set_calendar_token(u_id: i32, conn: &mut DBConnection) -> APIResult<String> {
+    let token = generate_token();
+    let token_hash = hash_token(&token);
+
+    diesel::insert_into(calendar_token::table)
+        .values((calendar_token::user_id.eq(u_id), calendar_token::token_hash.eq(&token_hash)))
+        .on_conflict(calendar_token::user_id)
+        .do_update()
+        .set(calendar_token::token_hash.eq(&token_hash))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(token)
+}
+
+pub async fn delete_calendar_token_of_user(u_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    diesel::delete(calendar_token::table)
+        .filter(calendar_token::user_id.eq(u_id))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/event/calendar.ics"
+)]
+pub async fn get_public_calendar(
+    mut conn: DBConnection,
+) -> APIResult<CalendarResponse> {
+    let entries = event::table
+        .filter(event::visible.eq(true))
+        .order(event::date)
+        .select((event::id, event::date, event::cancelled))
+        .get_results::<(i32, NaiveDateTime, bool)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?
+        .into_iter()
+        .map(|(id, date, cancelled)| CalendarEntry {
+            id,
+            date,
+            cancelled,
+            summary: CALENDAR_NAME.to_string(),
+        })
+        .collect();
+
+    Ok(calendar_response(build_calendar(entries)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/event/calendar/feed/{token}"
+)]
+pub async fn get_personal_calendar(
+    mut conn: DBConnection,
+    Path(token): Path<String>,
+) -> APIResult<CalendarResponse> {
+    let u_id = get_user_id_of_calendar_token(&token, &mut conn).await?;
+
+    let entries = event_user::table
+        .inner_join(event::table.on(event::id.eq(event_user::event_id)))
+        .filter(event_user::user_id.eq(u_id))
+        .filter(event_user::state.ne(EventUserState::Rejected))
+        .order(event::date)
+        .select((event::id, event::date, event::cancelled, event_user::state))
+        .get_results::<(i32, NaiveDateTime, bool, EventUserState)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?
+        .into_iter()
+        .map(|(id, date, cancelled, state)| CalendarEntry {
+            id,
+            date,
+            cancelled,
+            summary: format!("{CALENDAR_NAME} ({})", get_event_user_state_text(state)),
+        })
+        .collect();
+
+    Ok(calendar_response(build_calendar(entries)))
+}
+
+/// Creates the token if the user has none yet. The token is only returned on creation,
+/// afterwards this returns `None` and a new token has to be made with reset.
+#[utoipa::path(
+    get,
+    path = "/event/calendar/token/{user_id}"
+)]
+pub async fn get_calendar_token(
+    auth: AuthSession,
+    Path(u_id): Path<i32>,
+) -> APIResult<Json<Option<String>>> {
+    let mut conn = auth_to_id_is_me_or_i_am_admin(auth, u_id).await?;
+
+    let exists = calendar_token::table
+        .filter(calendar_token::user_id.eq(u_id))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)? > 0;
+
+    if exists {
+        return Ok(Json(None))
+    }
+
+    let token = set_calendar_token(u_id, &mut conn).await?;
+    Ok(Json(Some(token)))
+}
+
+/// Replaces the token, so a leaked feed url stops working.
+#[utoipa::path(
+    post,
+    path = "/event/calendar/token/{user_id}/reset"
+)]
+pub async fn reset_calendar_token(
+    auth: AuthSession,
+    Path(u_id): Path<i32>,
+) -> APIResult<Json<String>> {
+    let mut conn = auth_to_id_is_me_or_i_am_admin(auth, u_id).await?;
+    let token = set_calendar_token(u_id, &mut conn).await?;
+    Ok(Json(token))
+}
+
+pub fn add_calendar_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/event/calendar.ics", get(get_public_calendar))
+        .route("/event/calendar/feed/:token", get(get_personal_calendar))
+        .route("/event/calendar/token/:user_id", get(get_calendar_token))
+        .route("/event/calendar/token/:user_id/reset", post(reset_calendar_token))
+}
@@ -0,0 +1,150 @@
+use axum::extract::Path;
+use axum::{Json, Router};
+use axum::routing::{get, post};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use hmac::{Hmac, Mac};
+use scoped_futures::ScopedFutureExt;
+use sha2::Sha256;
+use utoipa::ToSchema;
+use crate::auth::AuthSession;
+use crate::auth::util::{auth_to_conn_expect_logged_in_and_check_attended, auth_to_id_is_me_or_i_am_admin};
+use crate::backend::Backend;
+use crate::config::check_in_secret;
+use crate::error::{APIError, APIResult};
+use crate::events::graduation::graduate_user_if_experienced;
+use crate::events::guests::get_event_user_id;
+use crate::events::user_action::{EventUserAction, log_user_action_from_event_user};
+use crate::events::users::{get_event_user_by_ids, EventUser, EventUserState};
+use crate::events::util::lock_event;
+use crate::schema::{event_user, user_data};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Only users holding a confirmed slot can check in, open offers are not admitted yet.
+const CHECK_IN_STATES: [EventUserState; 2] = [EventUserState::Registered, EventUserState::New];
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct CheckIn {
+    pub event_user: EventUser,
+    pub name: String,
+}
+
+fn new_check_in_mac(payload: &str) -> APIResult<HmacSha256> {
+    let secret = check_in_secret()?;
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).map_err(APIError::internal)?;
+    mac.update(payload.as_bytes());
+    Ok(mac)
+}
+
+/// `EventUser` does not carry the row id, so it is loaded on its own.
+/// The token is `event_id.user_id.event_user_id.signature`. The registration id makes sure
+/// a token stops working once the user unregisters and registers again.
+fn create_check_in_token(event_user: &EventUser, eu_id: i32) -> APIResult<String> {
+    let payload = format!("{}.{}.{}", event_user.event_id, event_user.user_id, eu_id);
+    let signature = new_check_in_mac(&payload)?.finalize().into_bytes();
+    Ok(format!("{payload}.{}", hex::encode(signature)))
+}
+
+/// Checks the signature and returns `(event_id, user_id, event_user_id)`.
+fn verify_check_in_token(token: &str) -> APIResult<(i32, i32, i32)> {
+    let (payload, signature) = token.trim().rsplit_once('.').ok_or(APIError::InvalidCheckInToken)?;
+    let signature = hex::decode(signature).map_err(|_| APIError::InvalidCheckInToken)?;
+
+    new_check_in_mac(payload)?
+        .verify_slice(&signature)
+        .map_err(|_| APIError::InvalidCheckInToken)?;
+
+    let ids = payload.split('.')
+        .map(|id| id.parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| APIError::InvalidCheckInToken)?;
+
+    match ids[..] {
+        [e_id, u_id, eu_id] => Ok((e_id, u_id, eu_id)),
+        _ => Err(APIError::InvalidCheckInToken),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/event/{event_id}/check_in_token/{user_id}"
+)]
+pub async fn get_check_in_token(
+    auth: AuthSession,
+    Path((e_id, u_id)): Path<(i32, i32)>,
+) -> APIResult<Json<String>> {
+    let mut conn = auth_to_id_is_me_or_i_am_admin(auth, u_id).await?;
+
+    let event_user = get_event_user_by_ids(e_id, u_id, &mut conn).await?;
+    if event_user.state == EventUserState::Rejected {
+        return Err(APIError::UserRejected)
+    }
+    if event_user.state == EventUserState::Cancelled {
+        return Err(APIError::EventCancelled)
+    }
+
+    let eu_id = get_event_user_id(e_id, u_id, &mut conn).await?;
+    Ok(Json(create_check_in_token(&event_user, eu_id)?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/event/{event_id}/check_in"
+)]
+pub async fn check_in(
+    auth: AuthSession,
+    Path(e_id): Path<i32>,
+    Json(token): Json<String>,
+) -> APIResult<Json<CheckIn>> {
+    let live = auth.backend.live.clone();
+    let mut conn = auth_to_conn_expect_logged_in_and_check_attended(auth, e_id).await?;
+
+    let (token_e_id, u_id, eu_id) = verify_check_in_token(&token)?;
+    if token_e_id != e_id {
+        return Err(APIError::CheckInWrongEvent)
+    }
+
+    let check_in = conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+
+        let event_user = get_event_user_by_ids(e_id, u_id, conn).await?;
+        if get_event_user_id(e_id, u_id, conn).await? != eu_id {
+            return Err(APIError::InvalidCheckInToken)
+        }
+        if !CHECK_IN_STATES.contains(&event_user.state) {
+            return Err(APIError::UserNotAdmitted)
+        }
+        if event_user.attended {
+            return Err(APIError::AlreadyCheckedIn)
+        }
+
+        let event_user = diesel::update(event_user::table)
+            .filter(event_user::id.eq(eu_id))
+            .set(event_user::attended.eq(true))
+            .returning(EventUser::as_select())
+            .get_result(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        log_user_action_from_event_user(event_user, EventUserAction::Attended, conn).await?;
+        graduate_user_if_experienced(u_id, conn).await?;
+
+        let name = user_data::table
+            .filter(user_data::user_id.eq(u_id))
+            .select(user_data::name)
+            .get_result::<String>(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        Ok(Json(CheckIn { event_user, name }))
+    }.scope_boxed()).await?;
+
+    live.notify(e_id);
+    Ok(check_in)
+}
+
+pub fn add_check_in_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/event/:event_id/check_in_token/:user_id", get(get_check_in_token))
+        .route("/event/:event_id/check_in", post(check_in))
+}
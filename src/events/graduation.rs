@@ -0,0 +1,78 @@
+use axum::{Json, Router};
+use axum::routing::post;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use crate::backend::{Backend, DBConnection};
+use crate::config::new_member_graduation_attendances;
+use crate::error::{APIError, APIResult};
+use crate::events::user_action::{EventUserAction, log_user_action};
+use crate::events::util::is_user_new;
+use crate::schema::{event, event_user, user_data};
+
+/// Returns the attended events of the user, the latest first.
+async fn get_attended_event_ids(u_id: i32, conn: &mut DBConnection) -> APIResult<Vec<i32>> {
+    event_user::table
+        .inner_join(event::table.on(event::id.eq(event_user::event_id)))
+        .filter(event_user::user_id.eq(u_id))
+        .filter(event_user::attended.eq(true))
+        .filter(event::cancelled.eq(false))
+        .order(event::date.desc())
+        .select(event::id)
+        .get_results(&mut conn.0)
+        .await
+        .map_err(APIError::internal)
+}
+
+/// Clears `new` once the user attended enough events and logs it with the event that made the difference.
+/// Returns whether the user graduated.
+pub async fn graduate_user_if_experienced(u_id: i32, conn: &mut DBConnection) -> APIResult<bool> {
+    let required = new_member_graduation_attendances();
+    if required <= 0 || !is_user_new(u_id, conn).await? {
+        return Ok(false)
+    }
+
+    let attended = get_attended_event_ids(u_id, conn).await?;
+    if (attended.len() as i64) < required {
+        return Ok(false)
+    }
+
+    diesel::update(user_data::table)
+        .filter(user_data::user_id.eq(u_id))
+        .set(user_data::new.eq(false))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    log_user_action(u_id, attended[0], EventUserAction::GraduatedNew, false, true, 0, conn).await?;
+
+    Ok(true)
+}
+
+/// Applies the graduation rule to all new users, for attendances recorded before the rule existed.
+#[utoipa::path(
+    post,
+    path = "/user_data/graduate_new"
+)]
+pub async fn post_graduate_new_users(
+    mut conn: DBConnection,
+) -> APIResult<Json<Vec<i32>>> {
+    let new_users = user_data::table
+        .filter(user_data::new.eq(true))
+        .select(user_data::user_id)
+        .get_results::<i32>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let mut graduated = vec![];
+    for u_id in new_users {
+        if graduate_user_if_experienced(u_id, &mut conn).await? {
+            graduated.push(u_id);
+        }
+    }
+
+    Ok(Json(graduated))
+}
+
+pub fn add_admin_graduation_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/user_data/graduate_new", post(post_graduate_new_users))
+}
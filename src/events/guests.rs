@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use axum::{Json, Router};
+use axum::extract::Path;
+use axum::routing::{get, post};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use scoped_futures::ScopedFutureExt;
+use utoipa::ToSchema;
+use crate::auth::AuthSession;
+use crate::auth::util::{auth_to_conn_expect_logged_in_and_check_attended, auth_to_id_is_me_or_i_am_admin, auth_to_id_is_me_or_i_am_admin_check_is_admin};
+use crate::backend::{Backend, DBConnection};
+use crate::error::{APIError, APIResult};
+use crate::events::policy::check_registration_open;
+use crate::events::slots::{after_guests_removed, check_change_guests_ok};
+use crate::events::user_action::{EventUserAction, log_user_action_from_event_user};
+use crate::events::users::EventUser;
+use crate::events::util::lock_event;
+use crate::schema::{event_guest, event_user, users};
+
+#[derive(Clone, serde::Serialize, serde::Deserialize, Queryable, Selectable, ToSchema, Debug, PartialEq)]
+#[diesel(table_name = event_guest)]
+pub struct EventGuest {
+    pub id: i32,
+    pub name: String,
+    pub user_id: Option<i32>,
+    pub attended: bool,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct NewEventGuest {
+    pub name: String,
+    pub user_id: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = event_guest)]
+struct EventGuestRow {
+    event_user_id: i32,
+    name: String,
+    user_id: Option<i32>,
+}
+
+pub async fn get_event_user_id(e_id: i32, u_id: i32, conn: &mut DBConnection) -> APIResult<i32> {
+    event_user::table
+        .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::user_id.eq(u_id))
+        .select(event_user::id)
+        .get_result(&mut conn.0)
+        .await
+        .map_err(|_| APIError::UserNotInEvent)
+}
+
+pub async fn get_guest_count(e_id: i32, u_id: i32, conn: &mut DBConnection) -> APIResult<i32> {
+    let event_user_id = get_event_user_id(e_id, u_id, conn).await?;
+    let count = event_guest::table
+        .filter(event_guest::event_user_id.eq(event_user_id))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(count as i32)
+}
+
+/// A guest can be linked to another existing user, who is not registered for the event themselves.
+async fn check_guest_user(e_id: i32, u_id: i32, guest_u_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    if guest_u_id == u_id {
+        return Err(APIError::GuestIsSelf)
+    }
+
+    let exists = users::table
+        .filter(users::id.eq(guest_u_id))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)? > 0;
+
+    if !exists {
+        return Err(APIError::GuestUserNotFound)
+    }
+
+    let registered = event_user::table
+        .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::user_id.eq(guest_u_id))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)? > 0;
+
+    if registered {
+        return Err(APIError::GuestAlreadyRegistered)
+    }
+
+    Ok(())
+}
+
+pub async fn insert_guests(event_user_id: i32, guests: Vec<NewEventGuest>, conn: &mut DBConnection) -> APIResult<()> {
+    let (e_id, u_id) = event_user::table
+        .filter(event_user::id.eq(event_user_id))
+        .select((event_user::event_id, event_user::user_id))
+        .get_result::<(i32, i32)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    for guest_u_id in guests.iter().filter_map(|guest| guest.user_id) {
+        check_guest_user(e_id, u_id, guest_u_id, conn).await?;
+    }
+
+    let rows: Vec<EventGuestRow> = guests.into_iter()
+        .map(|guest| EventGuestRow {
+            event_user_id,
+            name: guest.name,
+            user_id: guest.user_id,
+        })
+        .collect();
+
+    if rows.is_empty() {
+        return Ok(())
+    }
+
+    diesel::insert_into(event_guest::table)
+        .values(&rows)
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(())
+}
+
+pub async fn delete_guests_of_event_user(e_id: i32, u_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    diesel::delete(event_guest::table)
+        .filter(event_guest::event_user_id.eq_any(event_user::table
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::user_id.eq(u_id))
+            .select(event_user::id)))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(())
+}
+
+/// Removes all guests the user brought and unlinks the user from guest entries of others.
+pub async fn delete_guests_of_user(u_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    diesel::delete(event_guest::table)
+        .filter(event_guest::event_user_id.eq_any(event_user::table
+            .filter(event_user::user_id.eq(u_id))
+            .select(event_user::id)))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    diesel::update(event_guest::table)
+        .filter(event_guest::user_id.eq(u_id))
+        .set(event_guest::user_id.eq(None::<i32>))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(())
+}
+
+/// Returns the guests of all users in the event by the id of the user who brought them.
+pub async fn get_guests_of_event(e_id: i32, conn: &mut DBConnection) -> APIResult<HashMap<i32, Vec<EventGuest>>> {
+    let guests = event_guest::table
+        .inner_join(event_user::table)
+        .filter(event_user::event_id.eq(e_id))
+        .select((event_user::user_id, EventGuest::as_select()))
+        .order(event_guest::id.asc())
+        .get_results::<(i32, EventGuest)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let mut guests_by_user: HashMap<i32, Vec<EventGuest>> = HashMap::new();
+    for (u_id, guest) in guests {
+        guests_by_user.entry(u_id).or_default().push(guest);
+    }
+
+    Ok(guests_by_user)
+}
+
+async fn sync_guest_count(e_id: i32, u_id: i32, conn: &mut DBConnection) -> APIResult<EventUser> {
+    let count = get_guest_count(e_id, u_id, conn).await?;
+
+    diesel::update(event_user::table)
+        .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::user_id.eq(u_id))
+        .set(event_user::guests.eq(count))
+        .returning(EventUser::as_select())
+        .get_result(&mut conn.0)
+        .await
+        .map_err(APIError::internal)
+}
+
+#[utoipa::path(
+    get,
+    path = "/event/{event_id}/guests/{user_id}"
+)]
+pub async fn get_guests(
+    auth: AuthSession,
+    Path((e_id, u_id)): Path<(i32, i32)>,
+) -> APIResult<Json<Vec<EventGuest>>> {
+    let mut conn = auth_to_id_is_me_or_i_am_admin(auth, u_id).await?;
+    let event_user_id = get_event_user_id(e_id, u_id, &mut conn).await?;
+
+    let guests = event_guest::table
+        .filter(event_guest::event_user_id.eq(event_user_id))
+        .select(EventGuest::as_select())
+        .order(event_guest::id.asc())
+        .get_results(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(Json(guests))
+}
+
+#[utoipa::path(
+    post,
+    path = "/event/{event_id}/guests/{user_id}/add"
+)]
+pub async fn add_guest(
+    auth: AuthSession,
+    Path((e_id, u_id)): Path<(i32, i32)>,
+    Json(guest): Json<NewEventGuest>
+) -> APIResult<()> {
+    let live = auth.backend.live.clone();
+    let (admin, mut conn) = auth_to_id_is_me_or_i_am_admin_check_is_admin(auth, u_id).await?;
+    check_registration_open(e_id, admin, &mut conn).await?;
+
+    conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+
+        if !check_change_guests_ok(e_id, u_id, 1, conn).await? {
+            return Err(APIError::ChangeGuestsDenied)
+        }
+
+        let event_user_id = get_event_user_id(e_id, u_id, conn).await?;
+        insert_guests(event_user_id, vec![guest], conn).await?;
+
+        let event_user = sync_guest_count(e_id, u_id, conn).await?;
+        log_user_action_from_event_user(event_user, EventUserAction::ChangeGuests, conn).await
+    }.scope_boxed()).await?;
+
+    live.notify(e_id);
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/event/{event_id}/guests/{user_id}/remove/{guest_id}"
+)]
+pub async fn remove_guest(
+    auth: AuthSession,
+    Path((e_id, u_id, g_id)): Path<(i32, i32, i32)>,
+) -> APIResult<()> {
+    let live = auth.backend.live.clone();
+    let (admin, mut conn) = auth_to_id_is_me_or_i_am_admin_check_is_admin(auth, u_id).await?;
+    check_registration_open(e_id, admin, &mut conn).await?;
+
+    conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+
+        let event_user_id = get_event_user_id(e_id, u_id, conn).await?;
+        let removed = diesel::delete(event_guest::table)
+            .filter(event_guest::id.eq(g_id))
+            .filter(event_guest::event_user_id.eq(event_user_id))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        if removed == 0 {
+            return Err(APIError::GuestNotFound)
+        }
+
+        let event_user = sync_guest_count(e_id, u_id, conn).await?;
+        after_guests_removed(event_user, conn).await?;
+        log_user_action_from_event_user(event_user, EventUserAction::ChangeGuests, conn).await
+    }.scope_boxed()).await?;
+
+    live.notify(e_id);
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/event/{event_id}/guests/attended/{guest_id}"
+)]
+pub async fn set_guest_attended(
+    auth: AuthSession,
+    Path((e_id, g_id)): Path<(i32, i32)>,
+    Json(value): Json<bool>
+) -> APIResult<()> {
+    let live = auth.backend.live.clone();
+    let mut conn = auth_to_conn_expect_logged_in_and_check_attended(auth, e_id).await?;
+
+    let updated = diesel::update(event_guest::table)
+        .filter(event_guest::id.eq(g_id))
+        .filter(event_guest::event_user_id.eq_any(event_user::table
+            .filter(event_user::event_id.eq(e_id))
+            .select(event_user::id)))
+        .set(event_guest::attended.eq(value))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    if updated == 0 {
+        return Err(APIError::GuestNotFound)
+    }
+
+    live.notify(e_id);
+    Ok(())
+}
+
+pub fn add_event_guest_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/event/:event_id/guests/:user_id", get(get_guests))
+        .route("/event/:event_id/guests/:user_id/add", post(add_guest))
+        .route("/event/:event_id/guests/:user_id/remove/:guest_id", post(remove_guest))
+        .route("/event/:event_id/guests/attended/:guest_id", post(set_guest_attended))
+}
@@ -0,0 +1,148 @@
+use std::convert::Infallible;
+use axum::extract::Path;
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::Router;
+use axum::routing::get;
+use futures::{Stream, StreamExt};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+use utoipa::ToSchema;
+use crate::auth::AuthSession;
+use crate::backend::{Backend, DBConnection};
+use crate::error::APIResult;
+use crate::events::public::{query_event_public_data, PublicEventData};
+use crate::events::users::{query_event_users, PublicEventUserLists};
+use crate::permissions::{is_admin, is_verified};
+
+/// Notifications that queue up for a slow client before it skips ahead to the latest state.
+const LIVE_CHANNEL_CAPACITY: usize = 256;
+
+/// In-process hub that tells all connected clients which event changed.
+/// The clients load the new state themselves, so every client only sees what it is allowed to.
+#[derive(Clone)]
+pub struct LiveUpdates {
+    sender: broadcast::Sender<i32>,
+}
+
+impl LiveUpdates {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(LIVE_CHANNEL_CAPACITY);
+        LiveUpdates { sender }
+    }
+
+    pub fn notify(&self, e_id: i32) {
+        // Sending only fails if nobody is listening.
+        let _ = self.sender.send(e_id);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<i32> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for LiveUpdates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct LiveEventData {
+    pub data: PublicEventData,
+    pub users: Option<PublicEventUserLists>,
+}
+
+async fn get_live_event_data(e_id: i32, admin: bool, verified: bool, conn: &mut DBConnection) -> APIResult<LiveEventData> {
+    let data = query_event_public_data(e_id, admin, conn).await?;
+    let users = if verified { Some(query_event_users::<false>(conn, e_id).await?) } else { None };
+    Ok(LiveEventData { data, users })
+}
+
+fn to_sse_event(data: &LiveEventData) -> SseEvent {
+    SseEvent::default()
+        .event("update")
+        .json_data(data)
+        .unwrap_or_else(|_| SseEvent::default().event("error"))
+}
+
+struct LiveStream {
+    backend: Backend,
+    receiver: broadcast::Receiver<i32>,
+    e_id: i32,
+    admin: bool,
+    verified: bool,
+}
+
+impl LiveStream {
+    /// Waits until the event changed. Returns `false` once the hub is gone.
+    async fn changed(&mut self) -> bool {
+        loop {
+            match self.receiver.recv().await {
+                Ok(e_id) if e_id == self.e_id => return true,
+                Ok(_) => continue,
+                // Missed notifications might have been for this event, the next load is up to date anyway.
+                Err(RecvError::Lagged(_)) => return true,
+                Err(RecvError::Closed) => return false,
+            }
+        }
+    }
+
+    async fn load(&self) -> APIResult<LiveEventData> {
+        let mut conn = self.backend.get_connection().await?;
+        get_live_event_data(self.e_id, self.admin, self.verified, &mut conn).await
+    }
+}
+
+/// Streams the occupancy of the event as Server-Sent Events. The current state is sent on connect
+/// and again after every change. Verified users also get the participant lists.
+#[utoipa::path(
+    get,
+    path = "/event/{id}/live"
+)]
+pub async fn get_event_live(
+    auth: AuthSession,
+    Path(e_id): Path<i32>,
+) -> APIResult<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>> {
+    let backend = auth.backend.clone();
+    let mut conn = backend.get_connection().await?;
+
+    let (admin, verified) = match &auth.user {
+        Some(user) => (is_admin(&mut conn, user.id).await, is_verified(&mut conn, user.id).await),
+        None => (false, false),
+    };
+
+    let stream = LiveStream {
+        receiver: backend.live.subscribe(),
+        backend,
+        e_id,
+        admin,
+        verified,
+    };
+
+    // Load once before streaming, so an invisible or missing event fails the request.
+    let initial = get_live_event_data(e_id, admin, verified, &mut conn).await?;
+    drop(conn);
+
+    let updates = futures::stream::unfold(stream, |mut stream| async move {
+        loop {
+            if !stream.changed().await {
+                return None
+            }
+
+            match stream.load().await {
+                Ok(data) => return Some((Ok(to_sse_event(&data)), stream)),
+                Err(err) => warn!("Could not load live data of event {}: {err}", stream.e_id),
+            }
+        }
+    });
+
+    let events = futures::stream::once(async move { Ok(to_sse_event(&initial)) })
+        .chain(updates);
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+pub fn add_live_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/event/:id/live", get(get_event_live))
+}
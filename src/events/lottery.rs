@@ -0,0 +1,209 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use axum::extract::Path;
+use axum::{Json, Router};
+use axum::routing::post;
+use chrono::{Local, NaiveDateTime};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use rand::{Rng, SeedableRng};
+use rand::rngs::OsRng;
+use rand_chacha::ChaCha8Rng;
+use scoped_futures::ScopedFutureExt;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+use crate::auth::AuthSession;
+use crate::auth::util::auth_to_conn_expect_admin_or_organizer;
+use crate::backend::{Backend, DBConnection};
+use crate::error::{APIError, APIResult};
+use crate::events::slots::{move_up_new, move_up_register};
+use crate::events::user_action::{EventUserAction, log_user_action_from_event_user};
+use crate::events::users::{EventUser, EventUserState};
+use crate::events::util::{get_next_slot_index_with_states, get_slots_and_new_slots_of_event, is_user_new, lock_event};
+use crate::schema::{event, event_user, user_action};
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct LotteryResult {
+    pub seed: i64,
+    pub drawn: NaiveDateTime,
+    pub won: Vec<EventUser>,
+    pub lost: Vec<EventUser>,
+}
+
+/// Orders the pool by weighted random sampling without replacement (Efraimidis-Spirakis):
+/// every user gets the key `u^(1/weight)` and the highest keys are drawn first.
+/// The pool has to be in a fixed order, so the same seed always gives the same result.
+fn draw_order(pool: Vec<(EventUser, f64)>, seed: i64) -> Vec<EventUser> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed as u64);
+
+    let mut keyed = pool.into_iter()
+        .map(|(user, weight)| {
+            let u = 1.0 - rng.gen::<f64>();
+            (u.powf(1.0 / weight), user)
+        })
+        .collect::<Vec<_>>();
+
+    keyed.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    keyed.into_iter().map(|(_, user)| user).collect()
+}
+
+/// Each lottery the user lost on other events before `drawn` adds `lottery_loser_weight` to the base weight of 1.
+/// Only losses before the draw count, so the draw can be repeated later from the stored seed and draw time.
+async fn get_lottery_weight(e_id: i32, u_id: i32, loser_weight: f64, drawn: NaiveDateTime, conn: &mut DBConnection) -> APIResult<f64> {
+    if loser_weight <= 0.0 {
+        return Ok(1.0)
+    }
+
+    let lost = user_action::table
+        .filter(user_action::user_id.eq(u_id))
+        .filter(user_action::event_id.ne(e_id))
+        .filter(user_action::action.eq(EventUserAction::LotteryLost))
+        .filter(user_action::date.lt(drawn))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(1.0 + loser_weight * lost as f64)
+}
+
+/// Draws the lottery of the event. The drawn users are put on the waiting lists in drawn order and
+/// admitted by the regular move up, so slots, guests, role balance and offers work as without lottery.
+pub async fn draw_lottery(e_id: i32, conn: &mut DBConnection) -> APIResult<LotteryResult> {
+    conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+
+        let (lottery_mode, lottery_seed, loser_weight) = event::table
+            .filter(event::id.eq(e_id))
+            .select((event::lottery_mode, event::lottery_seed, event::lottery_loser_weight))
+            .get_result::<(bool, Option<i64>, f64)>(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        if !lottery_mode {
+            return Err(APIError::NoLottery)
+        }
+        if lottery_seed.is_some() {
+            return Err(APIError::LotteryAlreadyDrawn)
+        }
+
+        let seed = OsRng.gen::<i64>();
+        let drawn = Local::now().naive_local();
+        diesel::update(event::table)
+            .filter(event::id.eq(e_id))
+            .set((
+                event::lottery_seed.eq(seed),
+                event::lottery_drawn.eq(drawn),
+            ))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        let pool_users = event_user::table
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::state.eq(EventUserState::Lottery))
+            .order(event_user::id.asc())
+            .select(EventUser::as_select())
+            .get_results::<EventUser>(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        let mut pool = vec![];
+        for user in pool_users {
+            let weight = get_lottery_weight(e_id, user.user_id, loser_weight, drawn, conn).await?;
+            pool.push((user, weight));
+        }
+
+        let (_, new_slots) = get_slots_and_new_slots_of_event(e_id, conn).await?;
+        for user in draw_order(pool, seed) {
+            let new = new_slots != 0 && is_user_new(user.user_id, conn).await?;
+            let state = if new { EventUserState::WaitingNew } else { EventUserState::Waiting };
+            let slot = get_next_slot_index_with_states(e_id, &[EventUserState::Waiting, EventUserState::WaitingNew], conn).await?;
+            let new_slot = if new { get_next_slot_index_with_states(e_id, &[EventUserState::WaitingNew], conn).await? } else { 0 };
+
+            diesel::update(event_user::table)
+                .filter(event_user::event_id.eq(e_id))
+                .filter(event_user::user_id.eq(user.user_id))
+                .set((
+                    event_user::state.eq(state),
+                    event_user::slot.eq(slot),
+                    event_user::new_slot.eq(new_slot),
+                ))
+                .execute(&mut conn.0)
+                .await
+                .map_err(APIError::internal)?;
+        }
+
+        let mut won = move_up_register(e_id, conn).await?;
+        won.append(&mut move_up_new(e_id, conn).await?);
+        let won_ids = won.iter().map(|user| user.user_id).collect::<HashSet<_>>();
+
+        let lost = event_user::table
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::state.eq_any([EventUserState::Waiting, EventUserState::WaitingNew]))
+            .order(event_user::slot.asc())
+            .select(EventUser::as_select())
+            .get_results::<EventUser>(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?
+            .into_iter()
+            .filter(|user| !won_ids.contains(&user.user_id))
+            .collect::<Vec<_>>();
+
+        for user in won.iter().copied() {
+            log_user_action_from_event_user(user, EventUserAction::LotteryWon, conn).await?;
+        }
+        for user in lost.iter().copied() {
+            log_user_action_from_event_user(user, EventUserAction::LotteryLost, conn).await?;
+        }
+
+        Ok(LotteryResult { seed, drawn, won, lost })
+    }.scope_boxed()).await
+}
+
+/// Draws the lotteries of all events whose registration deadline has passed.
+pub async fn draw_due_lotteries(conn: &mut DBConnection) -> APIResult<Vec<i32>> {
+    let now = Local::now().naive_local();
+
+    let due = event::table
+        .filter(event::lottery_mode.eq(true))
+        .filter(event::lottery_seed.is_null())
+        .filter(event::cancelled.eq(false))
+        .filter(event::register_deadline.le(now))
+        .select(event::id)
+        .get_results::<i32>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let mut drawn = vec![];
+    for e_id in due {
+        match draw_lottery(e_id, conn).await {
+            Ok(result) => {
+                info!("Drew lottery of event {e_id} with seed {}: {} won, {} lost", result.seed, result.won.len(), result.lost.len());
+                drawn.push(e_id);
+            }
+            Err(err) => warn!("Could not draw lottery of event {e_id}: {err}"),
+        }
+    }
+
+    Ok(drawn)
+}
+
+#[utoipa::path(
+    post,
+    path = "/event/{id}/lottery/draw"
+)]
+pub async fn post_draw_lottery(
+    auth: AuthSession,
+    Path(e_id): Path<i32>,
+) -> APIResult<Json<LotteryResult>> {
+    let live = auth.backend.live.clone();
+    let mut conn = auth_to_conn_expect_admin_or_organizer(auth, e_id).await?;
+    let result = draw_lottery(e_id, &mut conn).await?;
+    live.notify(e_id);
+    Ok(Json(result))
+}
+
+pub fn add_lottery_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/event/:id/lottery/draw", post(post_draw_lottery))
+}
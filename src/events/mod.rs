@@ -2,7 +2,18 @@ pub mod users;
 pub mod user_action;
 pub mod public;
 pub mod slots;
-mod util;
+pub mod guests;
+pub mod series;
+pub mod policy;
+pub mod scheduler;
+pub mod calendar;
+pub mod check_in;
+pub mod reliability;
+pub mod graduation;
+pub mod lottery;
+pub mod waiting;
+pub mod live;
+pub mod util;
 
 use axum::{Json, Router};
 use axum::extract::Path;
@@ -10,15 +21,30 @@ use axum::routing::{get, post};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
+use scoped_futures::ScopedFutureExt;
+use tracing::warn;
 use utoipa::ToSchema;
+use crate::auth::AuthSession;
+use crate::auth::util::{auth_to_conn_expect_admin_or_organizer, auth_to_conn_expect_admin_or_organizer_check_is_admin};
 use crate::backend::{Backend, DBConnection};
 use crate::error::APIError;
-use crate::schema::event;
+use crate::events::public::EventDate;
+use crate::events::series::add_series_exception;
+use crate::events::slots::{move_down_new, move_down_register, move_up_new, move_up_register};
+use crate::events::user_action::{EventUserAction, log_user_action_from_event_user};
+use crate::events::users::{EventUser, EventUserState};
+use crate::events::util::{get_slots_and_new_slots_of_event, lock_event};
+use crate::mails::send_event_cancelled_mail;
+use crate::permissions::delete_event_permissions_of_event;
+use crate::schema::{event, event_user};
+use crate::schema::user_action as user_action_table;
+use crate::schema::users as users_table;
+use crate::user_data::get_user_data_by_id;
 use crate::error::APIResult;
 
 pub const CUSTOM_WORKSHOP: &str = "Custom";
 
-#[derive(serde::Serialize, serde::Deserialize, Queryable, Insertable, Selectable, AsChangeset, ToSchema, Debug, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize, Queryable, Insertable, Selectable, ToSchema, Debug, PartialEq)]
 #[diesel(table_name = event)]
 pub struct Event {
     pub id: i32,
@@ -31,7 +57,107 @@ pub struct Event {
     pub visible: bool,
     pub archive: bool,
     pub custom_workshop: String,
-    pub workshop_file: String
+    pub workshop_file: String,
+    pub offer_mode: bool,
+    pub offer_hours: i32,
+    pub balance_roles: bool,
+    pub target_role_factor: f64,
+    pub role_tolerance: f64,
+    pub series_id: Option<i32>,
+    pub cancelled: bool,
+    pub lottery_mode: bool,
+    pub lottery_loser_weight: f64,
+    pub lottery_seed: Option<i64>,
+    pub lottery_drawn: Option<NaiveDateTime>,
+}
+
+/// The columns `update_event` writes. `cancelled` is left out, only `cancel_event_and_log` changes it,
+/// and the lottery seed and draw time are only written by `draw_lottery`.
+#[derive(AsChangeset)]
+#[diesel(table_name = event)]
+struct UpdateEvent {
+    visible_date: NaiveDateTime,
+    register_deadline: NaiveDateTime,
+    date: NaiveDateTime,
+    archive_date: NaiveDateTime,
+    slots: i32,
+    new_slots: i32,
+    visible: bool,
+    archive: bool,
+    custom_workshop: String,
+    workshop_file: String,
+    offer_mode: bool,
+    offer_hours: i32,
+    balance_roles: bool,
+    target_role_factor: f64,
+    role_tolerance: f64,
+    series_id: Option<i32>,
+    lottery_mode: bool,
+    lottery_loser_weight: f64,
+}
+
+impl From<Event> for UpdateEvent {
+    fn from(event: Event) -> Self {
+        UpdateEvent {
+            visible_date: event.visible_date,
+            register_deadline: event.register_deadline,
+            date: event.date,
+            archive_date: event.archive_date,
+            slots: event.slots,
+            new_slots: event.new_slots,
+            visible: event.visible,
+            archive: event.archive,
+            custom_workshop: event.custom_workshop,
+            workshop_file: event.workshop_file,
+            offer_mode: event.offer_mode,
+            offer_hours: event.offer_hours,
+            balance_roles: event.balance_roles,
+            target_role_factor: event.target_role_factor,
+            role_tolerance: event.role_tolerance,
+            series_id: event.series_id,
+            lottery_mode: event.lottery_mode,
+            lottery_loser_weight: event.lottery_loser_weight,
+        }
+    }
+}
+
+/// The columns an organizer of the event may change. Publishing, archiving and the series stay with the admins.
+#[derive(AsChangeset)]
+#[diesel(table_name = event)]
+struct OrganizerUpdateEvent {
+    register_deadline: NaiveDateTime,
+    date: NaiveDateTime,
+    slots: i32,
+    new_slots: i32,
+    custom_workshop: String,
+    workshop_file: String,
+    offer_mode: bool,
+    offer_hours: i32,
+    balance_roles: bool,
+    target_role_factor: f64,
+    role_tolerance: f64,
+    lottery_mode: bool,
+    lottery_loser_weight: f64,
+}
+
+impl From<Event> for OrganizerUpdateEvent {
+    fn from(event: Event) -> Self {
+        OrganizerUpdateEvent {
+            register_deadline: event.register_deadline,
+            date: event.date,
+            slots: event.slots,
+            new_slots: event.new_slots,
+            custom_workshop: event.custom_workshop,
+            workshop_file: event.workshop_file,
+            offer_mode: event.offer_mode,
+            offer_hours: event.offer_hours,
+            balance_roles: event.balance_roles,
+            target_role_factor: event.target_role_factor,
+            role_tolerance: event.role_tolerance,
+            lottery_mode: event.lottery_mode,
+            lottery_loser_weight: event.lottery_loser_weight,
+        }
+    }
 }
 
 #[derive(serde::Deserialize, Insertable, ToSchema, Debug, PartialEq)]
@@ -46,7 +172,15 @@ pub struct NewEvent {
     pub visible: bool,
     pub archive: bool,
     pub custom_workshop: String,
-    pub workshop_file: String
+    pub workshop_file: String,
+    pub offer_mode: bool,
+    pub offer_hours: i32,
+    pub balance_roles: bool,
+    pub target_role_factor: f64,
+    pub role_tolerance: f64,
+    pub series_id: Option<i32>,
+    pub lottery_mode: bool,
+    pub lottery_loser_weight: f64,
 }
 
 #[utoipa::path(
@@ -65,26 +199,59 @@ pub async fn post_event(
     Ok(())
 }
 
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq, Default)]
+pub struct CapacityChange {
+    pub promoted: Vec<EventUser>,
+    pub demoted: Vec<EventUser>,
+}
+
 #[utoipa::path(
     post,
     path = "/event/{id}"
 )]
 pub async fn update_event(
-    mut conn: DBConnection,
+    auth: AuthSession,
     Path(e_id): Path<i32>,
     Json(event): Json<Event>
-) -> APIResult<()> {
+) -> APIResult<Json<CapacityChange>> {
+    let live = auth.backend.live.clone();
+    let (admin, mut conn) = auth_to_conn_expect_admin_or_organizer_check_is_admin(auth, e_id).await?;
     if e_id != event.id {
         return Err(APIError::EventIdsDontMatch)
     }
     
-    diesel::update(event::table)
-        .filter(event::id.eq(event.id))
-        .set(event)
-        .execute(&mut conn.0)
-        .await
-        .map_err(APIError::internal)?;
-    Ok(())
+    let change = conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+        let (old_slots, old_new_slots) = get_slots_and_new_slots_of_event(e_id, conn).await?;
+        let (slots, new_slots) = (event.slots, event.new_slots);
+
+        let update = diesel::update(event::table).filter(event::id.eq(e_id));
+        let updated = if admin {
+            update.set(UpdateEvent::from(event)).execute(&mut conn.0).await
+        } else {
+            update.set(OrganizerUpdateEvent::from(event)).execute(&mut conn.0).await
+        };
+        updated.map_err(APIError::internal)?;
+
+        let mut change = CapacityChange::default();
+
+        if slots > old_slots {
+            change.promoted.append(&mut move_up_register(e_id, conn).await?);
+        } else if slots < old_slots {
+            change.demoted.append(&mut move_down_register(e_id, conn).await?);
+        }
+
+        if new_slots > old_new_slots {
+            change.promoted.append(&mut move_up_new(e_id, conn).await?);
+        } else if new_slots < old_new_slots {
+            change.demoted.append(&mut move_down_new(e_id, conn).await?);
+        }
+
+        Ok(change)
+    }.scope_boxed()).await?;
+
+    live.notify(e_id);
+    Ok(Json(change))
 }
 
 #[utoipa::path(
@@ -92,9 +259,10 @@ pub async fn update_event(
     path = "/event/{id}"
 )]
 pub async fn get_event(
-    mut conn: DBConnection,
+    auth: AuthSession,
     Path(e_id): Path<i32>,
 ) -> APIResult<Json<Event>> {
+    let mut conn = auth_to_conn_expect_admin_or_organizer(auth, e_id).await?;
     let event = event::table
         .filter(event::id.eq(e_id))
         .select(Event::as_select())
@@ -112,14 +280,138 @@ pub async fn delete_event(
     mut conn: DBConnection,
     Path(e_id): Path<i32>,
 ) -> APIResult<()> {
-    diesel::delete(event::table)
+    if has_event_history(e_id, &mut conn).await? {
+        return Err(APIError::EventHasParticipants)
+    }
+
+    delete_event_and_add_exception(e_id, &mut conn).await
+}
+
+/// Deleted occurrences of a series are kept as exceptions, so they are not generated again.
+pub async fn delete_event_and_add_exception(e_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    conn.transaction(|conn| async move {
+        add_series_exception(e_id, conn).await?;
+        delete_event_permissions_of_event(conn, e_id).await?;
+
+        diesel::delete(event::table)
+            .filter(event::id.eq(e_id))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+        Ok(())
+    }.scope_boxed()).await
+}
+
+/// Events with registrations or logged actions can not be deleted without losing history, they have to be cancelled.
+pub async fn has_event_history(e_id: i32, conn: &mut DBConnection) -> APIResult<bool> {
+    let participants = event_user::table
+        .filter(event_user::event_id.eq(e_id))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let actions = user_action_table::table
+        .filter(user_action_table::event_id.eq(e_id))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(participants + actions > 0)
+}
+
+/// Marks the event as cancelled, logs the cancellation for every participant and moves them to `Cancelled`.
+/// The registrations are kept as history. Returns the participants with the state they had before, to notify them.
+pub async fn cancel_event_and_log(e_id: i32, conn: &mut DBConnection) -> APIResult<Vec<EventUser>> {
+    conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+
+        let cancelled = diesel::update(event::table)
+            .filter(event::id.eq(e_id))
+            .filter(event::cancelled.eq(false))
+            .set(event::cancelled.eq(true))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        if cancelled == 0 {
+            return Err(APIError::EventCancelled)
+        }
+
+        let participants = event_user::table
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::state.ne(EventUserState::Rejected))
+            .select(EventUser::as_select())
+            .get_results::<EventUser>(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        for participant in participants.iter().copied() {
+            log_user_action_from_event_user(participant, EventUserAction::EventCancelled, conn).await?;
+        }
+
+        diesel::update(event_user::table)
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::state.ne(EventUserState::Rejected))
+            .set((
+                event_user::state.eq(EventUserState::Cancelled),
+                event_user::offer_expires.eq(None::<NaiveDateTime>),
+            ))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        Ok(participants)
+    }.scope_boxed()).await
+}
+
+async fn notify_participant_cancelled(event_date: &EventDate, participant: &EventUser, conn: &mut DBConnection) -> APIResult<()> {
+    let mail = users_table::table
+        .filter(users_table::id.eq(participant.user_id))
+        .select(users_table::email)
+        .get_result::<String>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    let user_data = get_user_data_by_id(conn, participant.user_id).await?;
+
+    send_event_cancelled_mail(&mail, user_data, event_date, participant).await
+}
+
+/// Sends the cancellation mail to every participant. A failed lookup or mail is logged and does not stop the others.
+pub async fn notify_event_cancelled(e_id: i32, participants: &[EventUser], conn: &mut DBConnection) -> APIResult<()> {
+    let date = event::table
         .filter(event::id.eq(e_id))
-        .execute(&mut conn.0)
+        .select(event::date)
+        .get_result::<NaiveDateTime>(&mut conn.0)
         .await
         .map_err(APIError::internal)?;
+    let event_date = EventDate { id: e_id, date, cancelled: true };
+
+    for participant in participants {
+        if let Err(err) = notify_participant_cancelled(&event_date, participant, conn).await {
+            warn!("Could not send cancellation mail to user {}: {err}", participant.user_id);
+        }
+    }
+
     Ok(())
 }
 
+#[utoipa::path(
+    post,
+    path = "/event/{id}/cancel"
+)]
+pub async fn cancel_event(
+    auth: AuthSession,
+    Path(e_id): Path<i32>,
+) -> APIResult<()> {
+    let live = auth.backend.live.clone();
+    let mut conn = auth_to_conn_expect_admin_or_organizer(auth, e_id).await?;
+    let participants = cancel_event_and_log(e_id, &mut conn).await?;
+    live.notify(e_id);
+    notify_event_cancelled(e_id, &participants, &mut conn).await
+}
+
 #[utoipa::path(
     get,
     path = "/event/all"
@@ -135,8 +427,13 @@ pub async fn get_event_all(mut conn: DBConnection) -> APIResult<Json<Vec<Event>>
 
 pub fn add_admin_event_routes(router: Router<Backend>) -> Router<Backend> {
    router.route("/event", post(post_event))
-       .route("/event/:id", post(update_event))
-       .route("/event/:id", get(get_event))
        .route("/event/all", get(get_event_all))
        .route("/event/:id/delete", post(delete_event))
+}
+
+/// Routes for admins and the organizers of the event, the permission is checked in the handlers.
+pub fn add_event_organizer_routes(router: Router<Backend>) -> Router<Backend> {
+   router.route("/event/:id", post(update_event))
+       .route("/event/:id", get(get_event))
+       .route("/event/:id/cancel", post(cancel_event))
 }
\ No newline at end of file
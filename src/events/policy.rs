@@ -0,0 +1,60 @@
+use chrono::{Local, NaiveDateTime};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use crate::backend::DBConnection;
+use crate::error::{APIError, APIResult};
+use crate::schema::{event, users};
+
+/// Checks that the event is currently open for registration changes.
+/// The window starts at `visible_date` and ends at `register_deadline`. Admins are always allowed unless the event is cancelled.
+pub async fn check_registration_open(e_id: i32, admin: bool, conn: &mut DBConnection) -> APIResult<()> {
+    let cancelled = event::table
+        .filter(event::id.eq(e_id))
+        .select(event::cancelled)
+        .get_result::<bool>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    if cancelled {
+        return Err(APIError::EventCancelled)
+    }
+
+    if admin {
+        return Ok(())
+    }
+
+    let (visible, archive, visible_date, register_deadline) = event::table
+        .filter(event::id.eq(e_id))
+        .select((event::visible, event::archive, event::visible_date, event::register_deadline))
+        .get_result::<(bool, bool, NaiveDateTime, NaiveDateTime)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let now = Local::now().naive_local();
+
+    if !visible || now < visible_date {
+        return Err(APIError::EventNotVisible)
+    }
+
+    if archive || now > register_deadline {
+        return Err(APIError::RegistrationClosed)
+    }
+
+    Ok(())
+}
+
+/// Users have to confirm their email before they can register to events.
+pub async fn check_email_confirmed(u_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    let email_confirmed = users::table
+        .filter(users::id.eq(u_id))
+        .select(users::email_confirmed)
+        .get_result::<bool>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    if !email_confirmed {
+        return Err(APIError::EmailNotConfirmed)
+    }
+
+    Ok(())
+}
@@ -7,16 +7,17 @@ use diesel_async::RunQueryDsl;
 use utoipa::ToSchema;
 use crate::auth::{AuthSession};
 use crate::auth::util::{auth_to_conn_expect_logged_in_and_verified_check_is_admin, auth_to_conn_expect_logged_in_check_is_admin, auth_to_is_admin_and_conn};
-use crate::backend::{Backend};
+use crate::backend::{Backend, DBConnection};
 use crate::error::{APIError, APIResult};
-use crate::events::users::{EventUserState};
-use crate::events::util::{get_count_of_event_users_open_with_state, get_count_of_event_users_with_state, get_slots_and_description_of_event_with_admin_check};
+use crate::events::users::{EventUserState, NEW_STATES, REGISTERED_STATES};
+use crate::events::util::{get_admitted_role_factor_sum_and_count, get_count_of_event_users_open_with_state, get_count_of_event_users_with_state, get_role_balance_of_event, get_slots_and_description_of_event_with_admin_check};
 use crate::schema::{event};
 
 #[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
 pub struct EventDate {
     pub id: i32,
     pub date: NaiveDateTime,
+    pub cancelled: bool,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
@@ -36,6 +37,9 @@ pub struct LoggedInEventData {
     pub wait_count: i32,
     pub open_count: i32,
     pub open_new_count: i32,
+    pub lottery_count: i32,
+    pub role_balance: Option<f64>,
+    pub target_role_factor: Option<f64>,
     pub description: String,
 }
 
@@ -50,13 +54,13 @@ pub async fn get_event_dates(
 
     if admin {
         let event_dates = event::table
-            .select((event::id, event::date))
+            .select((event::id, event::date, event::cancelled))
             .get_results(&mut conn.0)
             .await
             .map_err(APIError::internal)?
             .into_iter()
-            .map(|(id, date)| {
-                EventDate{id, date}
+            .map(|(id, date, cancelled)| {
+                EventDate{id, date, cancelled}
             })
             .collect();
 
@@ -65,13 +69,14 @@ pub async fn get_event_dates(
 
     let event_dates = event::table
         .filter(event::visible.eq(true))
-        .select((event::id, event::date))
+        .filter(event::cancelled.eq(false))
+        .select((event::id, event::date, event::cancelled))
         .get_results(&mut conn.0)
         .await
         .map_err(APIError::internal)?
         .into_iter()
-        .map(|(id, date)| {
-            EventDate{id, date}
+        .map(|(id, date, cancelled)| {
+            EventDate{id, date, cancelled}
         })
         .collect();
     Ok(Json(event_dates))
@@ -88,17 +93,21 @@ pub async fn get_event_public_data(
     Path(e_id): Path<i32>,
 ) -> APIResult<Json<PublicEventData>> {
     let (admin, mut conn) = auth_to_is_admin_and_conn(auth).await?;
+    let data = query_event_public_data(e_id, admin, &mut conn).await?;
+    Ok(Json(data))
+}
 
-    let (slots, _, description) = get_slots_and_description_of_event_with_admin_check(e_id, admin, &mut conn).await?;
-    let register_count = get_count_of_event_users_with_state(e_id, &[EventUserState::Registered], &mut conn).await?;
-    let wait_count = get_count_of_event_users_with_state(e_id, &[EventUserState::Waiting, EventUserState::WaitingNew], &mut conn).await?;
+pub async fn query_event_public_data(e_id: i32, admin: bool, conn: &mut DBConnection) -> APIResult<PublicEventData> {
+    let (slots, _, description) = get_slots_and_description_of_event_with_admin_check(e_id, admin, conn).await?;
+    let register_count = get_count_of_event_users_with_state(e_id, &REGISTERED_STATES, conn).await?;
+    let wait_count = get_count_of_event_users_with_state(e_id, &[EventUserState::Waiting, EventUserState::WaitingNew], conn).await?;
 
-    Ok(Json(PublicEventData {
+    Ok(PublicEventData {
         slots,
         register_count,
         wait_count,
         description,
-    }))
+    })
 }
 
 #[utoipa::path(
@@ -112,11 +121,15 @@ pub async fn get_event_logged_in_data(
     let (admin, mut conn) = auth_to_conn_expect_logged_in_and_verified_check_is_admin(auth).await?;
 
     let (slots, new_slots, description) = get_slots_and_description_of_event_with_admin_check(e_id, admin, &mut conn).await?;
-    let register_count = get_count_of_event_users_with_state(e_id, &[EventUserState::Registered], &mut conn).await?;
-    let new_count = get_count_of_event_users_with_state(e_id, &[EventUserState::New], &mut conn).await?;
+    let register_count = get_count_of_event_users_with_state(e_id, &REGISTERED_STATES, &mut conn).await?;
+    let new_count = get_count_of_event_users_with_state(e_id, &NEW_STATES, &mut conn).await?;
     let wait_count = get_count_of_event_users_with_state(e_id, &[EventUserState::Waiting, EventUserState::WaitingNew], &mut conn).await?;
-    let open_count = get_count_of_event_users_open_with_state(e_id, &[EventUserState::Registered], &mut conn).await?;
-    let open_new_count = get_count_of_event_users_open_with_state(e_id, &[EventUserState::New], &mut conn).await?;
+    let open_count = get_count_of_event_users_open_with_state(e_id, &REGISTERED_STATES, &mut conn).await?;
+    let open_new_count = get_count_of_event_users_open_with_state(e_id, &NEW_STATES, &mut conn).await?;
+    let lottery_count = get_count_of_event_users_with_state(e_id, &[EventUserState::Lottery], &mut conn).await?;
+    let (role_factor_sum, admitted_count) = get_admitted_role_factor_sum_and_count(e_id, &mut conn).await?;
+    let role_balance = if admitted_count > 0 { Some(role_factor_sum / admitted_count as f64) } else { None };
+    let target_role_factor = get_role_balance_of_event(e_id, &mut conn).await?.map(|(target, _)| target);
 
     Ok(Json(LoggedInEventData {
         slots,
@@ -126,6 +139,9 @@ pub async fn get_event_logged_in_data(
         wait_count,
         open_count,
         open_new_count,
+        lottery_count,
+        role_balance,
+        target_role_factor,
         description,
     }))
 }
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use axum::extract::Path;
+use axum::{Json, Router};
+use axum::routing::get;
+use chrono::{Duration, Local, NaiveDateTime};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use utoipa::ToSchema;
+use crate::backend::{Backend, DBConnection};
+use crate::config::{late_cancel_hours, reliability_cooling_off_days, reliability_incident_threshold, reliability_policy, reliability_window_days, ReliabilityPolicy};
+use crate::error::{APIError, APIResult};
+use crate::events::user_action::EventUserAction;
+use crate::events::users::EventUserState;
+use crate::schema::{event, event_user, user_action};
+
+/// Incidents of a user in the reliability window. A no-show is a registration with a slot that was not attended,
+/// a late cancellation an unregistration while holding a slot shortly before the event.
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq, Default)]
+pub struct Reliability {
+    pub user_id: i32,
+    pub no_shows: i64,
+    pub late_cancellations: i64,
+    pub last_incident: Option<NaiveDateTime>,
+}
+
+impl Reliability {
+    fn add_incident(&mut self, date: NaiveDateTime) {
+        self.last_incident = self.last_incident.max(Some(date));
+    }
+
+    pub fn incidents(&self) -> i64 {
+        self.no_shows + self.late_cancellations
+    }
+}
+
+/// Returns `(user_id, event_date)` of every no-show in the window.
+/// Only events where attendance was recorded for at least one participant count, otherwise nobody was checked in.
+async fn get_no_shows(u_id: Option<i32>, since: NaiveDateTime, conn: &mut DBConnection) -> APIResult<Vec<(i32, NaiveDateTime)>> {
+    let now = Local::now().naive_local();
+    let checked_in = diesel::alias!(event_user as checked_in);
+
+    let mut query = event_user::table
+        .inner_join(event::table.on(event::id.eq(event_user::event_id)))
+        .filter(event_user::state.eq_any([EventUserState::Registered, EventUserState::New]))
+        .filter(event_user::attended.eq(false))
+        .filter(event::cancelled.eq(false))
+        .filter(event::date.lt(now))
+        .filter(event::date.ge(since))
+        .filter(event::id.eq_any(checked_in
+            .filter(checked_in.field(event_user::attended).eq(true))
+            .select(checked_in.field(event_user::event_id))))
+        .select((event_user::user_id, event::date))
+        .into_boxed();
+
+    if let Some(u_id) = u_id {
+        query = query.filter(event_user::user_id.eq(u_id));
+    }
+
+    query.get_results(&mut conn.0)
+        .await
+        .map_err(APIError::internal)
+}
+
+/// Returns `(user_id, unregister_date)` of every late cancellation in the window.
+async fn get_late_cancellations(u_id: Option<i32>, since: NaiveDateTime, conn: &mut DBConnection) -> APIResult<Vec<(i32, NaiveDateTime)>> {
+    let late = Duration::hours(late_cancel_hours());
+
+    let mut query = user_action::table
+        .inner_join(event::table)
+        .filter(user_action::action.eq(EventUserAction::Unregister))
+        .filter(user_action::in_waiting.eq(false))
+        .filter(event::cancelled.eq(false))
+        .filter(user_action::date.ge(since))
+        .select((user_action::user_id, user_action::date, event::date))
+        .into_boxed();
+
+    if let Some(u_id) = u_id {
+        query = query.filter(user_action::user_id.eq(u_id));
+    }
+
+    let unregistrations = query.get_results::<(i32, NaiveDateTime, NaiveDateTime)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(unregistrations.into_iter()
+        .filter(|(_, date, event_date)| *date > *event_date - late)
+        .map(|(u_id, date, _)| (u_id, date))
+        .collect())
+}
+
+async fn get_reliabilities(u_id: Option<i32>, conn: &mut DBConnection) -> APIResult<HashMap<i32, Reliability>> {
+    let since = Local::now().naive_local() - Duration::days(reliability_window_days());
+    let mut reliabilities = HashMap::<i32, Reliability>::new();
+
+    for (u_id, date) in get_no_shows(u_id, since, conn).await? {
+        let reliability = reliabilities.entry(u_id).or_insert_with(|| Reliability { user_id: u_id, ..Default::default() });
+        reliability.no_shows += 1;
+        reliability.add_incident(date);
+    }
+
+    for (u_id, date) in get_late_cancellations(u_id, since, conn).await? {
+        let reliability = reliabilities.entry(u_id).or_insert_with(|| Reliability { user_id: u_id, ..Default::default() });
+        reliability.late_cancellations += 1;
+        reliability.add_incident(date);
+    }
+
+    Ok(reliabilities)
+}
+
+pub async fn get_reliability_of_user(u_id: i32, conn: &mut DBConnection) -> APIResult<Reliability> {
+    let reliability = get_reliabilities(Some(u_id), conn).await?
+        .remove(&u_id)
+        .unwrap_or(Reliability { user_id: u_id, ..Default::default() });
+    Ok(reliability)
+}
+
+/// Applies the configured policy. Returns `true` if the user may only be put on the waiting list
+/// and fails if the user is in the cooling-off period.
+pub async fn check_reliability_policy(u_id: i32, conn: &mut DBConnection) -> APIResult<bool> {
+    let policy = reliability_policy();
+    if policy == ReliabilityPolicy::Off {
+        return Ok(false)
+    }
+
+    let reliability = get_reliability_of_user(u_id, conn).await?;
+    if reliability.incidents() < reliability_incident_threshold() {
+        return Ok(false)
+    }
+
+    match (policy, reliability.last_incident) {
+        (ReliabilityPolicy::WaitingList, _) => Ok(true),
+        (ReliabilityPolicy::CoolingOff, Some(last_incident)) => {
+            let blocked_until = last_incident + Duration::days(reliability_cooling_off_days());
+            if Local::now().naive_local() < blocked_until {
+                return Err(APIError::RegistrationBlocked)
+            }
+            Ok(false)
+        }
+        _ => Ok(false),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/{id}/reliability"
+)]
+pub async fn get_user_reliability(
+    mut conn: DBConnection,
+    Path(u_id): Path<i32>,
+) -> APIResult<Json<Reliability>> {
+    Ok(Json(get_reliability_of_user(u_id, &mut conn).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/reliability/all"
+)]
+pub async fn get_reliability_all(
+    mut conn: DBConnection,
+) -> APIResult<Json<Vec<Reliability>>> {
+    let mut reliabilities = get_reliabilities(None, &mut conn).await?
+        .into_values()
+        .collect::<Vec<_>>();
+    reliabilities.sort_by(|a, b| b.incidents().cmp(&a.incidents()).then(a.user_id.cmp(&b.user_id)));
+    Ok(Json(reliabilities))
+}
+
+pub fn add_admin_reliability_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/user/:id/reliability", get(get_user_reliability))
+        .route("/user/reliability/all", get(get_reliability_all))
+}
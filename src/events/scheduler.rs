@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use axum::{Json, Router};
+use axum::extract::State;
+use axum::routing::post;
+use chrono::Local;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+use crate::backend::{Backend, DBConnection};
+use crate::config::scheduler_interval_secs;
+use crate::error::{APIError, APIResult};
+use crate::events::live::LiveUpdates;
+use crate::events::lottery::draw_due_lotteries;
+use crate::events::series::generate_series_events;
+use crate::events::slots::expire_offers;
+use crate::events::users::EventUser;
+use crate::schema::event;
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct ScheduleResult {
+    pub published: Vec<i32>,
+    pub archived: Vec<i32>,
+    pub expired_offers: Vec<EventUser>,
+    pub generated: Vec<i32>,
+    pub lotteries: Vec<i32>,
+}
+
+async fn publish_due_events(conn: &mut DBConnection) -> APIResult<Vec<i32>> {
+    let now = Local::now().naive_local();
+
+    diesel::update(event::table)
+        .filter(event::visible.eq(false))
+        .filter(event::archive.eq(false))
+        .filter(event::cancelled.eq(false))
+        .filter(event::visible_date.le(now))
+        .filter(event::archive_date.gt(now))
+        .set(event::visible.eq(true))
+        .returning(event::id)
+        .get_results::<i32>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)
+}
+
+async fn archive_due_events(conn: &mut DBConnection) -> APIResult<Vec<i32>> {
+    let now = Local::now().naive_local();
+
+    diesel::update(event::table)
+        .filter(event::archive.eq(false))
+        .filter(event::archive_date.le(now))
+        .set(event::archive.eq(true))
+        .returning(event::id)
+        .get_results::<i32>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)
+}
+
+/// A failed step is logged and gives an empty result, so it does not hold up the other steps.
+fn step_result<T: Default>(step: &str, result: APIResult<T>) -> T {
+    result.unwrap_or_else(|err| {
+        warn!("Scheduler step {step} failed: {err}");
+        T::default()
+    })
+}
+
+/// Sets `visible` on events whose `visible_date` has passed and `archive` on events whose `archive_date` has passed.
+/// An admin who wants to hide an event again after its `visible_date` has to move the date as well.
+/// Also expires offers, draws due lotteries and generates the upcoming occurrences of event series.
+/// Every step runs on its own, the result contains what the successful steps did.
+pub async fn run_event_schedule(conn: &mut DBConnection) -> ScheduleResult {
+    let published = step_result("publish", publish_due_events(conn).await);
+    if !published.is_empty() {
+        info!("Scheduler published events {:?}", published);
+    }
+
+    let archived = step_result("archive", archive_due_events(conn).await);
+    if !archived.is_empty() {
+        info!("Scheduler archived events {:?}", archived);
+    }
+
+    let expired_offers = step_result("expire offers", expire_offers(conn).await);
+    for expired in &expired_offers {
+        info!("Scheduler expired the offer of user {} for event {}", expired.user_id, expired.event_id);
+    }
+
+    let lotteries = step_result("draw lotteries", draw_due_lotteries(conn).await);
+
+    let generated = step_result("generate series", generate_series_events(conn).await);
+    if !generated.is_empty() {
+        info!("Scheduler generated events {:?}", generated);
+    }
+
+    ScheduleResult {
+        published,
+        archived,
+        expired_offers,
+        generated,
+        lotteries,
+    }
+}
+
+/// Tells the live clients about the events whose participants were changed by the schedule.
+fn notify_schedule_changes(result: &ScheduleResult, live: &LiveUpdates) {
+    let changed = result.expired_offers.iter()
+        .map(|expired| expired.event_id)
+        .chain(result.published.iter().copied())
+        .chain(result.archived.iter().copied())
+        .chain(result.lotteries.iter().copied())
+        .collect::<HashSet<_>>();
+
+    for e_id in changed {
+        live.notify(e_id);
+    }
+}
+
+pub async fn start_event_scheduler(backend: Backend) {
+    let mut interval = tokio::time::interval(Duration::from_secs(scheduler_interval_secs()));
+
+    loop {
+        interval.tick().await;
+
+        match backend.get_connection().await {
+            Ok(mut conn) => {
+                let result = run_event_schedule(&mut conn).await;
+                notify_schedule_changes(&result, &backend.live);
+            }
+            Err(err) => warn!("Event scheduler failed: {err}"),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/event/schedule/run"
+)]
+pub async fn post_run_event_schedule(
+    State(backend): State<Backend>,
+    mut conn: DBConnection,
+) -> APIResult<Json<ScheduleResult>> {
+    let result = run_event_schedule(&mut conn).await;
+    notify_schedule_changes(&result, &backend.live);
+    Ok(Json(result))
+}
+
+pub fn add_admin_scheduler_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/event/schedule/run", post(post_run_event_schedule))
+}
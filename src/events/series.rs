@@ -0,0 +1,274 @@
+use axum::{Json, Router};
+use axum::extract::{Path, State};
+use axum::routing::{get, post};
+use chrono::{Duration, Local, NaiveDateTime};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use scoped_futures::ScopedFutureExt;
+use tracing::warn;
+use utoipa::ToSchema;
+use crate::backend::{Backend, DBConnection};
+use crate::error::{APIError, APIResult};
+use crate::events::{cancel_event_and_log, delete_event_and_add_exception, has_event_history, notify_event_cancelled};
+use crate::schema::{event, event_series, event_series_exception};
+
+/// Template and recurrence rule for events that repeat every `interval_days` starting at `first_date`.
+/// The offsets are in hours relative to the date of an occurrence, negative values lie before it.
+#[derive(serde::Serialize, serde::Deserialize, Queryable, Selectable, AsChangeset, ToSchema, Debug, PartialEq)]
+#[diesel(table_name = event_series)]
+pub struct EventSeries {
+    pub id: i32,
+    pub first_date: NaiveDateTime,
+    pub end_date: Option<NaiveDateTime>,
+    pub interval_days: i32,
+    pub generate_days_ahead: i32,
+    pub visible_offset_hours: i32,
+    pub register_deadline_offset_hours: i32,
+    pub archive_offset_hours: i32,
+    pub slots: i32,
+    pub new_slots: i32,
+    pub custom_workshop: String,
+    pub workshop_file: String,
+}
+
+#[derive(serde::Deserialize, Insertable, ToSchema, Debug, PartialEq)]
+#[diesel(table_name = event_series)]
+pub struct NewEventSeries {
+    pub first_date: NaiveDateTime,
+    pub end_date: Option<NaiveDateTime>,
+    pub interval_days: i32,
+    pub generate_days_ahead: i32,
+    pub visible_offset_hours: i32,
+    pub register_deadline_offset_hours: i32,
+    pub archive_offset_hours: i32,
+    pub slots: i32,
+    pub new_slots: i32,
+    pub custom_workshop: String,
+    pub workshop_file: String,
+}
+
+/// Offsets are checked relative to each other, so every occurrence becomes visible before its deadline and is archived last.
+fn validate_series(
+    interval_days: i32,
+    visible_offset_hours: i32,
+    register_deadline_offset_hours: i32,
+    archive_offset_hours: i32,
+) -> APIResult<()> {
+    if interval_days <= 0 {
+        return Err(APIError::InvalidSeries)
+    }
+    if visible_offset_hours > register_deadline_offset_hours || register_deadline_offset_hours > archive_offset_hours {
+        return Err(APIError::InvalidSeriesOffsets)
+    }
+    Ok(())
+}
+
+/// Occurrences are matched by the date they were generated for, so moving an event does not create a duplicate.
+async fn has_occurrence_or_exception(series: &EventSeries, date: NaiveDateTime, conn: &mut DBConnection) -> APIResult<bool> {
+    let events = event::table
+        .filter(event::series_id.eq(series.id))
+        .filter(event::occurrence_date.eq(date))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let exceptions = event_series_exception::table
+        .filter(event_series_exception::series_id.eq(series.id))
+        .filter(event_series_exception::date.eq(date))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(events + exceptions > 0)
+}
+
+/// Records the occurrence of the event as an exception of its series, so the scheduler does not generate it again.
+/// Does nothing for events that are not part of a series.
+pub async fn add_series_exception(e_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    let (series_id, date, occurrence_date) = event::table
+        .filter(event::id.eq(e_id))
+        .select((event::series_id, event::date, event::occurrence_date))
+        .get_result::<(Option<i32>, NaiveDateTime, Option<NaiveDateTime>)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let Some(s_id) = series_id else {
+        return Ok(())
+    };
+
+    diesel::insert_into(event_series_exception::table)
+        .values((
+            event_series_exception::series_id.eq(s_id),
+            event_series_exception::date.eq(occurrence_date.unwrap_or(date)),
+        ))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(())
+}
+
+async fn generate_events_of_series(series: &EventSeries, conn: &mut DBConnection) -> APIResult<Vec<i32>> {
+    validate_series(series.interval_days, series.visible_offset_hours, series.register_deadline_offset_hours, series.archive_offset_hours)?;
+
+    let now = Local::now().naive_local();
+    let horizon = now + Duration::days(series.generate_days_ahead as i64);
+
+    let mut generated = vec![];
+    let mut date = series.first_date;
+    while date <= horizon && series.end_date.is_none_or(|end_date| date <= end_date) {
+        if date >= now && !has_occurrence_or_exception(series, date, conn).await? {
+            // A parallel run may have generated the occurrence in the meantime, the unique index skips it then.
+            let e_id = diesel::insert_into(event::table)
+                .values((
+                    event::date.eq(date),
+                    event::visible_date.eq(date + Duration::hours(series.visible_offset_hours as i64)),
+                    event::register_deadline.eq(date + Duration::hours(series.register_deadline_offset_hours as i64)),
+                    event::archive_date.eq(date + Duration::hours(series.archive_offset_hours as i64)),
+                    event::slots.eq(series.slots),
+                    event::new_slots.eq(series.new_slots),
+                    event::visible.eq(false),
+                    event::archive.eq(false),
+                    event::custom_workshop.eq(&series.custom_workshop),
+                    event::workshop_file.eq(&series.workshop_file),
+                    event::series_id.eq(series.id),
+                    event::occurrence_date.eq(date),
+                ))
+                .on_conflict_do_nothing()
+                .returning(event::id)
+                .get_result::<i32>(&mut conn.0)
+                .await
+                .optional()
+                .map_err(APIError::internal)?;
+
+            generated.extend(e_id);
+        }
+
+        date += Duration::days(series.interval_days as i64);
+    }
+
+    Ok(generated)
+}
+
+/// Creates the missing occurrences of all series up to their generation horizon.
+/// Occurrences that already exist or were cancelled are never touched again.
+/// A series that fails is logged and skipped, so it does not block the others.
+pub async fn generate_series_events(conn: &mut DBConnection) -> APIResult<Vec<i32>> {
+    let all_series = event_series::table
+        .select(EventSeries::as_select())
+        .get_results(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let mut generated = vec![];
+    for series in all_series {
+        match generate_events_of_series(&series, conn).await {
+            Ok(mut generated_of_series) => generated.append(&mut generated_of_series),
+            Err(err) => warn!("Could not generate events of series {}: {err}", series.id),
+        }
+    }
+
+    Ok(generated)
+}
+
+#[utoipa::path(
+    post,
+    path = "/event_series"
+)]
+pub async fn post_event_series(
+    mut conn: DBConnection,
+    Json(new_series): Json<NewEventSeries>
+) -> APIResult<Json<Vec<i32>>> {
+    validate_series(new_series.interval_days, new_series.visible_offset_hours, new_series.register_deadline_offset_hours, new_series.archive_offset_hours)?;
+
+    let generated = conn.transaction(|conn| async move {
+        let series = diesel::insert_into(event_series::table)
+            .values(&new_series)
+            .returning(EventSeries::as_returning())
+            .get_result(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        generate_events_of_series(&series, conn).await
+    }.scope_boxed()).await?;
+
+    Ok(Json(generated))
+}
+
+#[utoipa::path(
+    post,
+    path = "/event_series/{id}"
+)]
+pub async fn update_event_series(
+    mut conn: DBConnection,
+    Path(s_id): Path<i32>,
+    Json(series): Json<EventSeries>
+) -> APIResult<Json<Vec<i32>>> {
+    if s_id != series.id {
+        return Err(APIError::SeriesIdsDontMatch)
+    }
+    validate_series(series.interval_days, series.visible_offset_hours, series.register_deadline_offset_hours, series.archive_offset_hours)?;
+
+    let generated = conn.transaction(|conn| async move {
+        diesel::update(event_series::table)
+            .filter(event_series::id.eq(s_id))
+            .set(&series)
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        generate_events_of_series(&series, conn).await
+    }.scope_boxed()).await?;
+
+    Ok(Json(generated))
+}
+
+#[utoipa::path(
+    get,
+    path = "/event_series/all"
+)]
+pub async fn get_event_series_all(mut conn: DBConnection) -> APIResult<Json<Vec<EventSeries>>> {
+    let series = event_series::table
+        .select(EventSeries::as_select())
+        .get_results(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    Ok(Json(series))
+}
+
+#[utoipa::path(
+    post,
+    path = "/event_series/{id}/cancel/{event_id}"
+)]
+pub async fn cancel_series_occurrence(
+    State(backend): State<Backend>,
+    mut conn: DBConnection,
+    Path((s_id, e_id)): Path<(i32, i32)>,
+) -> APIResult<()> {
+    event::table
+        .filter(event::id.eq(e_id))
+        .filter(event::series_id.eq(s_id))
+        .select(event::id)
+        .get_result::<i32>(&mut conn.0)
+        .await
+        .map_err(|_| APIError::EventNotInSeries)?;
+
+    if has_event_history(e_id, &mut conn).await? {
+        let participants = cancel_event_and_log(e_id, &mut conn).await?;
+        backend.live.notify(e_id);
+        return notify_event_cancelled(e_id, &participants, &mut conn).await
+    }
+
+    delete_event_and_add_exception(e_id, &mut conn).await?;
+    backend.live.notify(e_id);
+    Ok(())
+}
+
+pub fn add_admin_event_series_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/event_series", post(post_event_series))
+        .route("/event_series/all", get(get_event_series_all))
+        .route("/event_series/:id", post(update_event_series))
+        .route("/event_series/:id/cancel/:event_id", post(cancel_series_occurrence))
+}
@@ -1,103 +1,333 @@
+//! All functions in this file expect to run inside a transaction that holds the lock of the event (see `lock_event`).
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use chrono::{Duration, Local, NaiveDateTime};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
+use scoped_futures::ScopedFutureExt;
+use tracing::warn;
 use crate::backend::DBConnection;
 use crate::error::{APIError, APIResult};
-use crate::events::users::{EventUser, EventUserState};
-use crate::events::util::{get_count_of_event_users_with_state, get_count_of_event_users_with_state_expect_user_id, get_max_slot_index_with_state, get_next_waiting_event_users, get_next_waiting_new_event_users, get_slots_and_new_slots_of_event, is_event_user_states, is_user_new};
-use crate::schema::event_user;
+use crate::events::user_action::{EventUserAction, log_user_action_from_event_user};
+use crate::events::users::{EventUser, EventUserState, NEW_STATES, REGISTERED_STATES};
+use crate::events::util::{get_count_of_event_users_with_state, get_count_of_event_users_with_state_expect_user_id, get_next_slot_index_with_states, get_admitted_role_factor_sum_and_count, get_offer_hours_of_event, get_role_balance_of_event, get_role_factor_of_user, get_waiting_event_users_with_role_factor, get_slots_and_new_slots_of_event, is_event_user_states, is_lottery_pending, is_user_new, lock_event};
+use crate::events::guests::get_guest_count;
+use crate::events::reliability::check_reliability_policy;
+use crate::schema::{event_guest, event_user, user_action};
+
+
+/// A user keeps the roles balanced if the average role factor of the admitted users stays in the tolerance
+/// or gets closer to the target.
+fn keeps_role_balance((sum, count): (f64, i64), role_factor: f64, (target, tolerance): (f64, f64)) -> bool {
+    if count == 0 {
+        return true
+    }
+
+    let before = (sum / count as f64 - target).abs();
+    let after = ((sum + role_factor) / (count + 1) as f64 - target).abs();
+    after <= tolerance || after < before
+}
+
+async fn is_admission_balanced(e_id: i32, u_id: i32, conn: &mut DBConnection) -> APIResult<bool> {
+    let Some(balance) = get_role_balance_of_event(e_id, conn).await? else {
+        return Ok(true)
+    };
+
+    let admitted = get_admitted_role_factor_sum_and_count(e_id, conn).await?;
+    let role_factor = get_role_factor_of_user(u_id, conn).await?;
+    Ok(keeps_role_balance(admitted, role_factor, balance))
+}
 
+/// Returns the next waiting user who fits into the free capacity.
+/// With role balancing only the first waiting user of each role bucket is considered, so the order in a bucket is kept.
+async fn get_next_user_to_move_up(e_id: i32, new: bool, free_capacity: i32, conn: &mut DBConnection) -> APIResult<Option<EventUser>> {
+    let waiting = get_waiting_event_users_with_role_factor(e_id, new, conn).await?;
+
+    let Some(balance) = get_role_balance_of_event(e_id, conn).await? else {
+        return Ok(waiting.into_iter()
+            .next()
+            .map(|(user, _)| user)
+            .filter(|user| user.guests < free_capacity))
+    };
+
+    let admitted = get_admitted_role_factor_sum_and_count(e_id, conn).await?;
+    let mut seen_buckets: Vec<Option<Ordering>> = vec![];
+    for (user, role_factor) in waiting {
+        let bucket = role_factor.partial_cmp(&balance.0);
+        if seen_buckets.contains(&bucket) {
+            continue
+        }
+        seen_buckets.push(bucket);
+
+        if user.guests < free_capacity && keeps_role_balance(admitted, role_factor, balance) {
+            return Ok(Some(user))
+        }
+    }
+
+    Ok(None)
+}
 
-pub async fn get_user_slot(e_id: i32, u_id: i32, guests: i32, conn: &mut DBConnection) -> APIResult<(EventUserState, i32, i32)> {
-    let register_count = get_count_of_event_users_with_state(e_id, &[EventUserState::Registered], conn).await?;
+/// Admin and organizer actions bypass the reliability policy, like they bypass the registration window.
+pub async fn get_user_slot(e_id: i32, u_id: i32, guests: i32, bypass_policy: bool, conn: &mut DBConnection) -> APIResult<(EventUserState, i32, i32)> {
+    if is_lottery_pending(e_id, conn).await? {
+        return Ok((EventUserState::Lottery, 0, 0))
+    }
+
+    let register_count = get_count_of_event_users_with_state(e_id, &REGISTERED_STATES, conn).await?;
     let (slots, new_slots) = get_slots_and_new_slots_of_event(e_id, conn).await?;
+    let waiting_only = !bypass_policy && check_reliability_policy(u_id, conn).await?;
+    // Users with a bad reliability record are only admitted by moving up from the waiting list.
+    let admit = !waiting_only && is_admission_balanced(e_id, u_id, conn).await?;
     
     // If there is still space in the register list use that.
-    if (register_count + guests + 1) <= slots {
+    if admit && (register_count + guests + 1) <= slots {
         return Ok((EventUserState::Registered, 0, 0))
     }
     
     if new_slots != 0 && is_user_new(u_id, conn).await? {
-        let new_register_count = get_count_of_event_users_with_state(e_id, &[EventUserState::New], conn).await?;
+        let new_register_count = get_count_of_event_users_with_state(e_id, &NEW_STATES, conn).await?;
 
         // If there is still space in the extra slots for new people register list use that.
-        if (new_register_count + guests + 1) <= new_slots {
+        if admit && (new_register_count + guests + 1) <= new_slots {
             return Ok((EventUserState::New, 0, 0))
         }
 
-        let slot_index = get_max_slot_index_with_state(e_id, EventUserState::Waiting, conn).await?;
-        let new_slot_index = get_max_slot_index_with_state(e_id, EventUserState::WaitingNew, conn).await?;
+        let slot_index = get_next_slot_index_with_states(e_id, &[EventUserState::Waiting, EventUserState::WaitingNew], conn).await?;
+        let new_slot_index = get_next_slot_index_with_states(e_id, &[EventUserState::WaitingNew], conn).await?;
         return Ok((EventUserState::WaitingNew, slot_index, new_slot_index));
     }
 
-    let slot_index = get_max_slot_index_with_state(e_id, EventUserState::Waiting, conn).await?;
+    let slot_index = get_next_slot_index_with_states(e_id, &[EventUserState::Waiting, EventUserState::WaitingNew], conn).await?;
     return Ok((EventUserState::Waiting, slot_index, 0));
 }
 
-pub async fn move_up_register(e_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+/// In offer mode a moved up user only gets an offer for the slot, which has to be confirmed in time.
+fn get_admission(state: EventUserState, offer_hours: Option<i32>) -> (EventUserState, Option<NaiveDateTime>, EventUserAction) {
+    match offer_hours {
+        Some(hours) => {
+            let offered_state = if state == EventUserState::New { EventUserState::OfferedNew } else { EventUserState::Offered };
+            let expires = Local::now().naive_local() + Duration::hours(hours as i64);
+            (offered_state, Some(expires), EventUserAction::Offered)
+        }
+        None => (state, None, EventUserAction::GetSlot),
+    }
+}
+
+pub async fn move_up_register(e_id: i32, conn: &mut DBConnection) -> APIResult<Vec<EventUser>> {
     let (slots, _) = get_slots_and_new_slots_of_event(e_id, conn).await?;
+    let (state, offer_expires, action) = get_admission(EventUserState::Registered, get_offer_hours_of_event(e_id, conn).await?);
     
-    let mut register_count = get_count_of_event_users_with_state(e_id, &[EventUserState::Registered], conn).await?;
-    let mut next_user = get_next_waiting_event_users(e_id, conn).await.ok();
+    let mut promoted = vec![];
+    let mut register_count = get_count_of_event_users_with_state(e_id, &REGISTERED_STATES, conn).await?;
+    let mut next_user = get_next_user_to_move_up(e_id, false, slots - register_count, conn).await?;
     
-    while next_user.is_some() && (register_count + next_user.unwrap().guests + 1) <= slots {
-        let user = next_user.unwrap();
-        diesel::update(event_user::table)
+    while let Some(user) = next_user {
+        let promoted_user = diesel::update(event_user::table)
             .filter(event_user::event_id.eq(e_id))
             .filter(event_user::user_id.eq(user.user_id))
-            .set(event_user::state.eq(EventUserState::Registered))
-            .execute(&mut conn.0)
+            .set((
+                event_user::state.eq(state),
+                event_user::offer_expires.eq(offer_expires),
+            ))
+            .returning(EventUser::as_select())
+            .get_result(&mut conn.0)
             .await
             .map_err(APIError::internal)?;
 
-        diesel::update(event_user::table)
+        remove_from_waiting_queues(user, conn).await?;
+        log_user_action_from_event_user(promoted_user, action, conn).await?;
+        promoted.push(promoted_user);
+
+        register_count = get_count_of_event_users_with_state(e_id, &REGISTERED_STATES, conn).await?;
+        next_user = get_next_user_to_move_up(e_id, false, slots - register_count, conn).await?;
+    }
+    
+    Ok(promoted)
+}
+
+pub async fn move_up_new(e_id: i32, conn: &mut DBConnection) -> APIResult<Vec<EventUser>> {
+    let (_, new_slots) = get_slots_and_new_slots_of_event(e_id, conn).await?;
+    let (state, offer_expires, action) = get_admission(EventUserState::New, get_offer_hours_of_event(e_id, conn).await?);
+
+    let mut promoted = vec![];
+    let mut new_count = get_count_of_event_users_with_state(e_id, &NEW_STATES, conn).await?;
+    let mut next_user = get_next_user_to_move_up(e_id, true, new_slots - new_count, conn).await?;
+
+    while let Some(user) = next_user {
+        let promoted_user = diesel::update(event_user::table)
             .filter(event_user::event_id.eq(e_id))
-            .filter(event_user::state.eq_any([EventUserState::Waiting, EventUserState::WaitingNew]))
-            .set(event_user::slot.eq(event_user::slot - 1))
-            .execute(&mut conn.0)
+            .filter(event_user::user_id.eq(user.user_id))
+            .set((
+                event_user::state.eq(state),
+                event_user::offer_expires.eq(offer_expires),
+            ))
+            .returning(EventUser::as_select())
+            .get_result(&mut conn.0)
             .await
             .map_err(APIError::internal)?;
 
-        register_count = get_count_of_event_users_with_state(e_id, &[EventUserState::Registered], conn).await?;
-        next_user = get_next_waiting_event_users(e_id, conn).await.ok();
+        remove_from_waiting_queues(user, conn).await?;
+        log_user_action_from_event_user(promoted_user, action, conn).await?;
+        promoted.push(promoted_user);
+
+        new_count = get_count_of_event_users_with_state(e_id, &NEW_STATES, conn).await?;
+        next_user = get_next_user_to_move_up(e_id, true, new_slots - new_count, conn).await?;
     }
-    
-    Ok(())
+
+    Ok(promoted)
 }
 
-pub async fn move_up_new(e_id: i32, conn: &mut DBConnection) -> APIResult<()> {
-    let (_, new_slots) = get_slots_and_new_slots_of_event(e_id, conn).await?;
+/// Returns the users with the given states in the order they lose their slot: open offers first,
+/// then the most recently admitted.
+async fn get_event_users_by_admission_desc(e_id: i32, states: &[EventUserState], conn: &mut DBConnection) -> APIResult<Vec<EventUser>> {
+    let mut users = event_user::table
+        .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::state.eq_any(states))
+        .select(EventUser::as_select())
+        .get_results(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let admission_dates: HashMap<i32, Option<NaiveDateTime>> = user_action::table
+        .filter(user_action::event_id.eq(e_id))
+        .filter(user_action::action.eq_any([EventUserAction::Register, EventUserAction::GetSlot, EventUserAction::Offered, EventUserAction::NotRejected]))
+        .group_by(user_action::user_id)
+        .select((user_action::user_id, diesel::dsl::max(user_action::date)))
+        .get_results::<(i32, Option<NaiveDateTime>)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?
+        .into_iter()
+        .collect();
 
-    let mut new_count = get_count_of_event_users_with_state(e_id, &[EventUserState::New], conn).await?;
-    let mut next_user = get_next_waiting_new_event_users(e_id, conn).await.ok();
+    let is_offer = |user: &EventUser| user.state == EventUserState::Offered || user.state == EventUserState::OfferedNew;
+    users.sort_by(|a, b| {
+        let a_date = admission_dates.get(&a.user_id).copied().flatten();
+        let b_date = admission_dates.get(&b.user_id).copied().flatten();
+        is_offer(b).cmp(&is_offer(a)).then(b_date.cmp(&a_date))
+    });
 
-    while next_user.is_some() && (new_count + next_user.unwrap().guests + 1) <= new_slots {
-        let user = next_user.unwrap();
-        diesel::update(event_user::table)
+    Ok(users)
+}
+
+/// Withdraws open offers and moves the most recently admitted users back to the head of the waiting list
+/// until the registered users fit into the slots.
+pub async fn move_down_register(e_id: i32, conn: &mut DBConnection) -> APIResult<Vec<EventUser>> {
+    let (slots, _) = get_slots_and_new_slots_of_event(e_id, conn).await?;
+    let mut register_count = get_count_of_event_users_with_state(e_id, &REGISTERED_STATES, conn).await?;
+
+    let mut to_demote = vec![];
+    for user in get_event_users_by_admission_desc(e_id, &REGISTERED_STATES, conn).await? {
+        if register_count <= slots {
+            break
+        }
+        register_count -= user.guests + 1;
+        to_demote.push(user);
+    }
+
+    if to_demote.is_empty() {
+        return Ok(vec![])
+    }
+
+    // Keep the order in which the users got their slots.
+    to_demote.reverse();
+
+    diesel::update(event_user::table)
+        .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::state.eq_any([EventUserState::Waiting, EventUserState::WaitingNew]))
+        .set(event_user::slot.eq(event_user::slot + to_demote.len() as i32))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let mut demoted = vec![];
+    for (index, user) in to_demote.into_iter().enumerate() {
+        let demoted_user = diesel::update(event_user::table)
             .filter(event_user::event_id.eq(e_id))
             .filter(event_user::user_id.eq(user.user_id))
-            .set(event_user::state.eq(EventUserState::New))
-            .execute(&mut conn.0)
+            .set((
+                event_user::state.eq(EventUserState::Waiting),
+                event_user::slot.eq(index as i32),
+                event_user::new_slot.eq(0),
+                event_user::offer_expires.eq(None::<NaiveDateTime>),
+            ))
+            .returning(EventUser::as_select())
+            .get_result(&mut conn.0)
             .await
             .map_err(APIError::internal)?;
 
-        diesel::update(event_user::table)
+        log_user_action_from_event_user(demoted_user, EventUserAction::LostSlot, conn).await?;
+        demoted.push(demoted_user);
+    }
+
+    Ok(demoted)
+}
+
+/// Withdraws open offers and moves the most recently admitted new users back to the head of both waiting lists
+/// until they fit into the new slots.
+pub async fn move_down_new(e_id: i32, conn: &mut DBConnection) -> APIResult<Vec<EventUser>> {
+    let (_, new_slots) = get_slots_and_new_slots_of_event(e_id, conn).await?;
+    let mut new_count = get_count_of_event_users_with_state(e_id, &NEW_STATES, conn).await?;
+
+    let mut to_demote = vec![];
+    for user in get_event_users_by_admission_desc(e_id, &NEW_STATES, conn).await? {
+        if new_count <= new_slots {
+            break
+        }
+        new_count -= user.guests + 1;
+        to_demote.push(user);
+    }
+
+    if to_demote.is_empty() {
+        return Ok(vec![])
+    }
+
+    to_demote.reverse();
+    let count = to_demote.len() as i32;
+
+    diesel::update(event_user::table)
+        .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::state.eq_any([EventUserState::Waiting, EventUserState::WaitingNew]))
+        .set(event_user::slot.eq(event_user::slot + count))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    diesel::update(event_user::table)
+        .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::state.eq(EventUserState::WaitingNew))
+        .set(event_user::new_slot.eq(event_user::new_slot + count))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let mut demoted = vec![];
+    for (index, user) in to_demote.into_iter().enumerate() {
+        let demoted_user = diesel::update(event_user::table)
             .filter(event_user::event_id.eq(e_id))
-            .filter(event_user::state.eq(EventUserState::WaitingNew))
-            .set(event_user::new_slot.eq(event_user::new_slot - 1))
-            .execute(&mut conn.0)
+            .filter(event_user::user_id.eq(user.user_id))
+            .set((
+                event_user::state.eq(EventUserState::WaitingNew),
+                event_user::slot.eq(index as i32),
+                event_user::new_slot.eq(index as i32),
+                event_user::offer_expires.eq(None::<NaiveDateTime>),
+            ))
+            .returning(EventUser::as_select())
+            .get_result(&mut conn.0)
             .await
             .map_err(APIError::internal)?;
 
-        new_count = get_count_of_event_users_with_state(e_id, &[EventUserState::New], conn).await?;
-        next_user = get_next_waiting_new_event_users(e_id, conn).await.ok();
+        log_user_action_from_event_user(demoted_user, EventUserAction::LostSlot, conn).await?;
+        demoted.push(demoted_user);
     }
 
-    Ok(())
+    Ok(demoted)
 }
 
 async fn move_up_waiting(e_id: i32, slot: i32, conn: &mut DBConnection) -> APIResult<()> {
     diesel::update(event_user::table)
         .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::state.eq_any([EventUserState::Waiting, EventUserState::WaitingNew]))
         .filter(event_user::slot.ge(slot))
         .set(event_user::slot.eq(event_user::slot - 1))
         .execute(&mut conn.0)
@@ -110,6 +340,7 @@ async fn move_up_waiting(e_id: i32, slot: i32, conn: &mut DBConnection) -> APIRe
 async fn move_up_waiting_new(e_id: i32, new_slot: i32, conn: &mut DBConnection) -> APIResult<()> {
     diesel::update(event_user::table)
         .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::state.eq(EventUserState::WaitingNew))
         .filter(event_user::new_slot.ge(new_slot))
         .set(event_user::new_slot.eq(event_user::new_slot - 1))
         .execute(&mut conn.0)
@@ -119,44 +350,126 @@ async fn move_up_waiting_new(e_id: i32, new_slot: i32, conn: &mut DBConnection)
     Ok(())
 }
 
-pub async fn after_unregister(event_user: EventUser, conn: &mut DBConnection) -> APIResult<()> {
-    if event_user.state == EventUserState::Registered {
+/// Closes the gaps a user leaves in the waiting queues. `event_user` holds the state from before the user left the queue.
+async fn remove_from_waiting_queues(event_user: EventUser, conn: &mut DBConnection) -> APIResult<()> {
+    if event_user.state == EventUserState::Waiting {
+        move_up_waiting(event_user.event_id, event_user.slot, conn).await?;
+    }
+
+    if event_user.state == EventUserState::WaitingNew {
+        move_up_waiting(event_user.event_id, event_user.slot, conn).await?;
+        move_up_waiting_new(event_user.event_id, event_user.new_slot, conn).await?;
+    }
+
+    Ok(())
+}
+
+/// With role balancing a new registration can allow waiting users of the other role to move up.
+pub async fn after_register(e_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    if get_role_balance_of_event(e_id, conn).await?.is_some() {
+        move_up_register(e_id, conn).await?;
+        move_up_new(e_id, conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Fewer guests of an admitted user can free slots for waiting users.
+pub async fn after_guests_removed(event_user: EventUser, conn: &mut DBConnection) -> APIResult<()> {
+    if REGISTERED_STATES.contains(&event_user.state) {
         move_up_register(event_user.event_id, conn).await?;
-        return Ok(())
     }
 
-    if event_user.state == EventUserState::Registered {
+    if NEW_STATES.contains(&event_user.state) {
         move_up_new(event_user.event_id, conn).await?;
-        return Ok(())
     }
 
-    if event_user.state == EventUserState::Waiting {
-        move_up_waiting(event_user.event_id, event_user.slot, conn).await?;
+    Ok(())
+}
+
+pub async fn after_unregister(event_user: EventUser, conn: &mut DBConnection) -> APIResult<()> {
+    if REGISTERED_STATES.contains(&event_user.state) {
+        move_up_register(event_user.event_id, conn).await?;
         return Ok(())
     }
 
-    if event_user.state == EventUserState::WaitingNew {
-        move_up_waiting(event_user.event_id, event_user.slot, conn).await?;
-        move_up_waiting_new(event_user.event_id, event_user.new_slot, conn).await?;
+    if NEW_STATES.contains(&event_user.state) {
+        move_up_new(event_user.event_id, conn).await?;
         return Ok(())
     }
-    
-    Ok(())
+
+    remove_from_waiting_queues(event_user, conn).await
+}
+
+/// Removes users whose offer has expired from their events and passes the offer on to the next waiting user.
+pub async fn expire_offers(conn: &mut DBConnection) -> APIResult<Vec<EventUser>> {
+    let now = Local::now().naive_local();
+
+    let event_ids = event_user::table
+        .filter(event_user::state.eq_any([EventUserState::Offered, EventUserState::OfferedNew]))
+        .filter(event_user::offer_expires.lt(now))
+        .select(event_user::event_id)
+        .distinct()
+        .get_results::<i32>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let mut expired = vec![];
+    for e_id in event_ids {
+        let result = conn.transaction(|conn| async move {
+            lock_event(e_id, conn).await?;
+
+            diesel::delete(event_guest::table)
+                .filter(event_guest::event_user_id.eq_any(event_user::table
+                    .filter(event_user::event_id.eq(e_id))
+                    .filter(event_user::state.eq_any([EventUserState::Offered, EventUserState::OfferedNew]))
+                    .filter(event_user::offer_expires.lt(now))
+                    .select(event_user::id)))
+                .execute(&mut conn.0)
+                .await
+                .map_err(APIError::internal)?;
+
+            let expired_users = diesel::delete(event_user::table)
+                .filter(event_user::event_id.eq(e_id))
+                .filter(event_user::state.eq_any([EventUserState::Offered, EventUserState::OfferedNew]))
+                .filter(event_user::offer_expires.lt(now))
+                .returning(EventUser::as_select())
+                .get_results::<EventUser>(&mut conn.0)
+                .await
+                .map_err(APIError::internal)?;
+
+            for expired_user in expired_users.iter().copied() {
+                log_user_action_from_event_user(expired_user, EventUserAction::OfferExpired, conn).await?;
+                after_unregister(expired_user, conn).await?;
+            }
+
+            Ok(expired_users)
+        }.scope_boxed()).await;
+
+        match result {
+            Ok(mut expired_in_event) => expired.append(&mut expired_in_event),
+            Err(err) => warn!("Could not expire offers of event {e_id}: {err}"),
+        }
+    }
+
+    Ok(expired)
 }
 
-pub async fn check_change_guests_ok(e_id: i32, u_id: i32, guests: i32, conn: &mut DBConnection) -> APIResult<bool> {
+/// Checks if the user can bring `added_guests` more guests than the guest entries they already have.
+pub async fn check_change_guests_ok(e_id: i32, u_id: i32, added_guests: i32, conn: &mut DBConnection) -> APIResult<bool> {
     let (slots, new_slots) = get_slots_and_new_slots_of_event(e_id, conn).await?;
+    let guests = get_guest_count(e_id, u_id, conn).await? + added_guests;
     
-    if is_event_user_states(e_id, u_id, &[EventUserState::Registered], conn).await {
+    if is_event_user_states(e_id, u_id, &REGISTERED_STATES, conn).await {
         let register_count = get_count_of_event_users_with_state_expect_user_id(
-            e_id, u_id, &[EventUserState::Registered], conn).await?;
+            e_id, u_id, &REGISTERED_STATES, conn).await?;
         
         return Ok((register_count + guests + 1 ) <= slots)
     }
 
-    if is_event_user_states(e_id, u_id, &[EventUserState::New], conn).await {
+    if is_event_user_states(e_id, u_id, &NEW_STATES, conn).await {
         let new_count = get_count_of_event_users_with_state_expect_user_id(
-            e_id, u_id, &[EventUserState::New], conn).await?;
+            e_id, u_id, &NEW_STATES, conn).await?;
 
         return Ok((new_count + guests + 1 ) <= new_slots)
     }
@@ -11,7 +11,7 @@ use crate::backend::{Backend, DBConnection};
 use crate::error::APIError;
 use crate::schema::{user_action};
 use crate::error::APIResult;
-use crate::events::users::{EventUser, EventUserState};
+use crate::events::users::{EventUser, EventUserState, NEW_STATES};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde_repr::Serialize_repr, serde_repr::Deserialize_repr)]
 #[derive(diesel_derive_enum::DbEnum)]
@@ -26,6 +26,14 @@ pub enum EventUserAction {
     ChangeGuests,
     Attended,
     NotAttended,
+    LostSlot,
+    Offered,
+    OfferConfirmed,
+    OfferExpired,
+    EventCancelled,
+    GraduatedNew,
+    LotteryWon,
+    LotteryLost,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Insertable, AsChangeset, Queryable, Selectable, ToSchema, Debug, PartialEq)]
@@ -43,10 +51,10 @@ pub struct UserAction {
 pub async fn log_user_action_from_event_user(
     event_user: EventUser,
     action: EventUserAction,
-    conn: DBConnection
+    conn: &mut DBConnection
 ) -> APIResult<()> {
     let waiting = event_user.state == EventUserState::Waiting || event_user.state == EventUserState::WaitingNew;
-    let new = event_user.state == EventUserState::New || event_user.state == EventUserState::WaitingNew;
+    let new = NEW_STATES.contains(&event_user.state) || event_user.state == EventUserState::WaitingNew;
     log_user_action(event_user.user_id, event_user.event_id, action, waiting, new, event_user.guests, conn).await?;
 
     Ok(())
@@ -59,7 +67,7 @@ pub async fn log_user_action(
     in_waiting: bool, 
     in_new: bool, 
     guests: i32,
-    mut conn: DBConnection
+    conn: &mut DBConnection
 ) -> APIResult<()> {
     diesel::insert_into(user_action::table)
         .values(UserAction {
@@ -2,19 +2,24 @@ use crate::auth::{AuthSession};
 use axum::{Json, Router};
 use axum::extract::Path;
 use axum::routing::{get, post};
+use chrono::{Local, NaiveDateTime};
 use diesel::prelude::*;
 use utoipa::ToSchema;
-use crate::auth::util::{auth_to_conn_expect_logged_in_and_check_attended, auth_to_conn_expect_logged_in_and_verified, auth_to_id_is_me_or_i_am_admin};
+use crate::auth::util::{auth_to_conn_expect_admin_or_organizer, auth_to_conn_expect_logged_in_and_check_attended, auth_to_conn_expect_logged_in_and_verified, auth_to_id_is_me_or_i_am_admin, auth_to_id_is_me_or_i_am_admin_check_is_admin};
 use crate::backend::{Backend, DBConnection};
 use diesel_async::RunQueryDsl;
+use scoped_futures::ScopedFutureExt;
 use crate::error::APIError;
 use crate::schema::{event_user, user_data};
 use crate::user_data::UserData;
 use crate::error::APIResult;
-use crate::events::slots::{after_unregister, check_change_guests_ok, get_user_slot};
+use crate::events::policy::{check_email_confirmed, check_registration_open};
+use crate::events::guests::{delete_guests_of_event_user, EventGuest, get_guests_of_event, insert_guests, NewEventGuest};
+use crate::events::graduation::graduate_user_if_experienced;
+use crate::events::slots::{after_register, after_unregister, get_user_slot};
 use crate::events::user_action::{EventUserAction, log_user_action_from_event_user};
-use crate::events::util::is_user_in_event;
-use crate::schema::event_user::{attended, guests};
+use crate::events::util::{is_event_user_states, is_user_in_event, lock_event};
+use crate::schema::event_user::attended;
 
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde_repr::Serialize_repr, serde_repr::Deserialize_repr, Ord, PartialOrd)]
@@ -27,8 +32,19 @@ pub enum EventUserState {
     Rejected, 
     New,
     WaitingNew,
+    Offered,
+    OfferedNew,
+    Lottery,
+    Cancelled,
 }
 
+/// States that take up one of the regular slots of an event.
+pub const REGISTERED_STATES: [EventUserState; 2] = [EventUserState::Registered, EventUserState::Offered];
+/// States that take up one of the new slots of an event.
+pub const NEW_STATES: [EventUserState; 2] = [EventUserState::New, EventUserState::OfferedNew];
+/// States that take up any slot of an event.
+pub const ADMITTED_STATES: [EventUserState; 4] = [EventUserState::Registered, EventUserState::Offered, EventUserState::New, EventUserState::OfferedNew];
+
 #[derive(Copy, Clone, serde::Serialize, serde::Deserialize, Insertable, AsChangeset, Queryable, Selectable, ToSchema, Debug, PartialEq)]
 #[diesel(table_name = event_user)]
 pub struct EventUser {
@@ -39,6 +55,7 @@ pub struct EventUser {
     pub state: EventUserState,
     pub guests: i32,
     pub attended: bool,
+    pub offer_expires: Option<NaiveDateTime>,
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
@@ -53,6 +70,8 @@ pub struct PublicEventUser {
     pub state: EventUserState,
     pub guests: i32,
     pub attended: Option<bool>,
+    pub offer_expires: Option<NaiveDateTime>,
+    pub guest_list: Option<Vec<EventGuest>>,
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
@@ -60,6 +79,8 @@ pub struct PublicEventUserLists {
     pub registered: Vec<PublicEventUser>,
     pub new: Vec<PublicEventUser>,
     pub waiting: Vec<PublicEventUser>,
+    pub lottery: Vec<PublicEventUser>,
+    pub rejected: Vec<PublicEventUser>,
 }
 
 fn get_public_user<const ADMIN: bool, const CHECK_ATTENDED: bool>((eu, ud): (EventUser, UserData)) -> PublicEventUser {
@@ -75,6 +96,8 @@ fn get_public_user<const ADMIN: bool, const CHECK_ATTENDED: bool>((eu, ud): (Eve
             state: eu.state,
             guests: eu.guests,
             attended: Some(eu.attended),
+            offer_expires: eu.offer_expires,
+            guest_list: None,
         }
     }
 
@@ -90,6 +113,8 @@ fn get_public_user<const ADMIN: bool, const CHECK_ATTENDED: bool>((eu, ud): (Eve
             state: eu.state,
             guests: eu.guests,
             attended: Some(eu.attended),
+            offer_expires: eu.offer_expires,
+            guest_list: None,
         }
     }
     
@@ -104,6 +129,8 @@ fn get_public_user<const ADMIN: bool, const CHECK_ATTENDED: bool>((eu, ud): (Eve
         state: eu.state,
         guests: eu.guests,
         attended: None,
+        offer_expires: eu.offer_expires,
+        guest_list: None,
     }
 }
 
@@ -148,9 +175,10 @@ pub async fn get_event_users(
     path = "/event/{id}/users/admin"
 )]
 pub async fn get_event_users_admin(
-    mut conn: DBConnection,
+    auth: AuthSession,
     Path(e_id): Path<i32>,
 ) -> APIResult<Json<PublicEventUserLists>> {
+    let mut conn = auth_to_conn_expect_admin_or_organizer(auth, e_id).await?;
     let users = query_event_users::<true>(&mut conn, e_id).await?;
     Ok(Json(users))
 
@@ -171,13 +199,15 @@ pub async fn query_event_users<const ADMIN: bool>(conn: &mut DBConnection, e_id:
     let mut registered = vec![];
     let mut new = vec![];
     let mut waiting = vec![];
+    let mut lottery = vec![];
+    let mut rejected = vec![];
     for user in users {
-        if user.state == EventUserState::Registered {
+        if REGISTERED_STATES.contains(&user.state) {
             registered.push(user);
             continue
         }
 
-        if user.state == EventUserState::New {
+        if NEW_STATES.contains(&user.state) {
             new.push(user);
             continue
         }
@@ -186,16 +216,30 @@ pub async fn query_event_users<const ADMIN: bool>(conn: &mut DBConnection, e_id:
             waiting.push(user);
             continue
         }
+
+        if user.state == EventUserState::Lottery {
+            lottery.push(user);
+            continue
+        }
+
+        if ADMIN && user.state == EventUserState::Rejected {
+            rejected.push(user);
+            continue
+        }
     }
 
     registered.sort_by(|a, b| {a.name.cmp(&b.name)});
     new.sort_by(|a, b| {a.name.cmp(&b.name)});
     waiting.sort_by(|a, b| {a.slot.cmp(&b.slot)});
+    lottery.sort_by(|a, b| {a.name.cmp(&b.name)});
+    rejected.sort_by(|a, b| {a.name.cmp(&b.name)});
 
     let user_list = PublicEventUserLists {
         registered,
         new,
         waiting,
+        lottery,
+        rejected,
     };
 
     Ok(user_list)
@@ -209,7 +253,7 @@ pub async fn get_event_users_check_attended(
     auth: AuthSession,
     Path(e_id): Path<i32>,
 ) -> APIResult<Json<Vec<PublicEventUser>>> {
-    let mut conn = auth_to_conn_expect_logged_in_and_check_attended(auth).await?;
+    let mut conn = auth_to_conn_expect_logged_in_and_check_attended(auth, e_id).await?;
     
     let mut users: Vec<PublicEventUser> = event_user::table
         .filter(event_user::event_id.eq(e_id))
@@ -222,12 +266,53 @@ pub async fn get_event_users_check_attended(
         .map(get_public_user::<false, true>)
         .collect();
 
+    let mut guests = get_guests_of_event(e_id, &mut conn).await?;
+    for user in users.iter_mut() {
+        user.guest_list = Some(guests.remove(&user.user_id).unwrap_or_default());
+    }
+
     users.sort_by(|a, b| {a.name.cmp(&b.name)});
     
     Ok(Json(users))
 
 }
 
+/// Registers the user with their guests. The event is locked, so parallel registrations can not take the same slot.
+pub async fn register_event_user(e_id: i32, u_id: i32, new_guests: Vec<NewEventGuest>, admin: bool, conn: &mut DBConnection) -> APIResult<()> {
+    conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+        let g = new_guests.len() as i32;
+
+        if is_user_in_event(e_id, u_id, conn).await {
+            return Err(APIError::UserAlreadyRegistered);
+        }
+
+        let (state, slot, new_slot) = get_user_slot(e_id, u_id, g, admin, conn).await?;
+
+        let event_user = EventUser{
+            user_id: u_id,
+            event_id: e_id,
+            slot,
+            new_slot,
+            state,
+            guests: g,
+            attended: false,
+            offer_expires: None,
+        };
+
+        let event_user_id = diesel::insert_into(event_user::table)
+            .values(&event_user)
+            .returning(event_user::id)
+            .get_result::<i32>(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        insert_guests(event_user_id, new_guests, conn).await?;
+        log_user_action_from_event_user(event_user, EventUserAction::Register, conn).await?;
+        after_register(e_id, conn).await
+    }.scope_boxed()).await
+}
+
 #[utoipa::path(
     post,
     path = "/event/{event_id}/register/{user_id}"
@@ -235,34 +320,18 @@ pub async fn get_event_users_check_attended(
 pub async fn register_to_event(
     auth: AuthSession,
     Path((e_id, u_id)): Path<(i32, i32)>,
-    Json(g): Json<i32>
+    Json(new_guests): Json<Vec<NewEventGuest>>
 ) -> APIResult<()> {
-    let mut conn = auth_to_id_is_me_or_i_am_admin(auth, u_id).await?;
-
-    if is_user_in_event(e_id, u_id, &mut conn).await {
-        return Err(APIError::UserAlreadyRegistered);
+    let live = auth.backend.live.clone();
+    let (admin, mut conn) = auth_to_id_is_me_or_i_am_admin_check_is_admin(auth, u_id).await?;
+    check_registration_open(e_id, admin, &mut conn).await?;
+    if !admin {
+        check_email_confirmed(u_id, &mut conn).await?;
     }
-    
-    let (state, slot, new_slot) = get_user_slot(e_id, u_id, g, &mut conn).await?;
-    
-    let event_user = EventUser{
-        user_id: u_id,
-        event_id: e_id,
-        slot,
-        new_slot,
-        state,
-        guests: g,
-        attended: false,
-    };
-    
-    diesel::insert_into(event_user::table)
-        .values(&event_user)
-        .execute(&mut conn.0)
-        .await
-        .map_err(APIError::internal)?;
 
-    log_user_action_from_event_user(event_user, EventUserAction::Register, conn).await?;
+    register_event_user(e_id, u_id, new_guests, admin, &mut conn).await?;
 
+    live.notify(e_id);
     Ok(())
 }
 
@@ -274,48 +343,76 @@ pub async fn unregister_from_event(
     auth: AuthSession,
     Path((e_id, u_id)): Path<(i32, i32)>,
 ) -> APIResult<()> {
-    let mut conn = auth_to_id_is_me_or_i_am_admin(auth, u_id).await?;
-    
-    let event_user = diesel::delete(event_user::table)
-        .filter(event_user::event_id.eq(e_id))
-        .filter(event_user::user_id.eq(u_id))
-        .returning(EventUser::as_select())
-        .get_result(&mut conn.0)
-        .await
-        .map_err(APIError::internal)?;
+    let live = auth.backend.live.clone();
+    let (admin, mut conn) = auth_to_id_is_me_or_i_am_admin_check_is_admin(auth, u_id).await?;
+    check_registration_open(e_id, admin, &mut conn).await?;
 
-    after_unregister(event_user, &mut conn).await?;
-    log_user_action_from_event_user(event_user, EventUserAction::Unregister, conn).await?;
-    
+    conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+
+        if !admin && is_event_user_states(e_id, u_id, &[EventUserState::Rejected], conn).await {
+            return Err(APIError::UserRejected)
+        }
+
+        delete_guests_of_event_user(e_id, u_id, conn).await?;
+
+        let event_user = diesel::delete(event_user::table)
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::user_id.eq(u_id))
+            .returning(EventUser::as_select())
+            .get_result(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        after_unregister(event_user, conn).await?;
+        log_user_action_from_event_user(event_user, EventUserAction::Unregister, conn).await
+    }.scope_boxed()).await?;
+
+    live.notify(e_id);
     Ok(())
 }
 
 #[utoipa::path(
     post,
-    path = "/event/{event_id}/change_guests/{user_id}"
+    path = "/event/{event_id}/confirm_offer/{user_id}"
 )]
-pub async fn change_guests(
+pub async fn confirm_offer(
     auth: AuthSession,
     Path((e_id, u_id)): Path<(i32, i32)>,
-    Json(g): Json<i32>
 ) -> APIResult<()> {
+    let live = auth.backend.live.clone();
     let mut conn = auth_to_id_is_me_or_i_am_admin(auth, u_id).await?;
-    
-    if !check_change_guests_ok(e_id, u_id, g, &mut conn).await? {
-        return Err(APIError::ChangeGuestsDenied)
-    }
-    
-    let event_user = diesel::update(event_user::table)
-        .filter(event_user::event_id.eq(e_id))
-        .filter(event_user::user_id.eq(u_id))
-        .set(guests.eq(g))
-        .returning(EventUser::as_select())
-        .get_result(&mut conn.0)
-        .await
-        .map_err(APIError::internal)?;
 
-    log_user_action_from_event_user(event_user, EventUserAction::ChangeGuests, conn).await?;
+    conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+
+        let event_user = get_event_user_by_ids(e_id, u_id, conn).await?;
+        let state = match event_user.state {
+            EventUserState::Offered => EventUserState::Registered,
+            EventUserState::OfferedNew => EventUserState::New,
+            _ => return Err(APIError::NoOffer),
+        };
 
+        if event_user.offer_expires.is_some_and(|expires| expires < Local::now().naive_local()) {
+            return Err(APIError::OfferExpired)
+        }
+
+        let event_user = diesel::update(event_user::table)
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::user_id.eq(u_id))
+            .set((
+                event_user::state.eq(state),
+                event_user::offer_expires.eq(None::<NaiveDateTime>),
+            ))
+            .returning(EventUser::as_select())
+            .get_result(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        log_user_action_from_event_user(event_user, EventUserAction::OfferConfirmed, conn).await
+    }.scope_boxed()).await?;
+
+    live.notify(e_id);
     Ok(())
 }
 
@@ -328,7 +425,8 @@ pub async fn set_attended(
     Path((e_id, u_id)): Path<(i32, i32)>,
     Json(value): Json<bool>
 ) -> APIResult<()> {
-    let mut conn = auth_to_conn_expect_logged_in_and_check_attended(auth).await?;
+    let live = auth.backend.live.clone();
+    let mut conn = auth_to_conn_expect_logged_in_and_check_attended(auth, e_id).await?;
     
     let event_user = diesel::update(event_user::table)
         .filter(event_user::event_id.eq(e_id))
@@ -339,21 +437,244 @@ pub async fn set_attended(
         .await
         .map_err(APIError::internal)?;
 
-    log_user_action_from_event_user(event_user, if value { EventUserAction::Attended } else { EventUserAction::NotAttended }, conn).await?;
+    log_user_action_from_event_user(event_user, if value { EventUserAction::Attended } else { EventUserAction::NotAttended }, &mut conn).await?;
+
+    if value {
+        graduate_user_if_experienced(u_id, &mut conn).await?;
+    }
 
+    live.notify(e_id);
     Ok(())
 }
 
-pub fn add_admin_event_user_routes(router: Router<Backend>) -> Router<Backend> {
-    router.route("/event/:event_id/users/admin", get(get_event_users_admin))
+#[utoipa::path(
+    post,
+    path = "/event/{event_id}/reject/{user_id}"
+)]
+pub async fn reject_event_user(
+    auth: AuthSession,
+    Path((e_id, u_id)): Path<(i32, i32)>,
+) -> APIResult<()> {
+    let live = auth.backend.live.clone();
+    let mut conn = auth_to_conn_expect_admin_or_organizer(auth, e_id).await?;
+    conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+
+        let event_user = get_event_user_by_ids(e_id, u_id, conn).await?;
+        if event_user.state == EventUserState::Rejected {
+            return Err(APIError::UserRejected)
+        }
+
+        diesel::update(event_user::table)
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::user_id.eq(u_id))
+            .set((
+                event_user::state.eq(EventUserState::Rejected),
+                event_user::slot.eq(0),
+                event_user::new_slot.eq(0),
+            ))
+            .execute(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        after_unregister(event_user, conn).await?;
+        log_user_action_from_event_user(event_user, EventUserAction::Rejected, conn).await
+    }.scope_boxed()).await?;
+
+    live.notify(e_id);
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/event/{event_id}/unreject/{user_id}"
+)]
+pub async fn unreject_event_user(
+    auth: AuthSession,
+    Path((e_id, u_id)): Path<(i32, i32)>,
+) -> APIResult<()> {
+    let live = auth.backend.live.clone();
+    let mut conn = auth_to_conn_expect_admin_or_organizer(auth, e_id).await?;
+    conn.transaction(|conn| async move {
+        lock_event(e_id, conn).await?;
+
+        let event_user = get_event_user_by_ids(e_id, u_id, conn).await?;
+        if event_user.state != EventUserState::Rejected {
+            return Err(APIError::UserNotRejected)
+        }
+
+        let (state, slot, new_slot) = get_user_slot(e_id, u_id, event_user.guests, true, conn).await?;
+
+        let event_user = diesel::update(event_user::table)
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::user_id.eq(u_id))
+            .set((
+                event_user::state.eq(state),
+                event_user::slot.eq(slot),
+                event_user::new_slot.eq(new_slot),
+            ))
+            .returning(EventUser::as_select())
+            .get_result(&mut conn.0)
+            .await
+            .map_err(APIError::internal)?;
+
+        log_user_action_from_event_user(event_user, EventUserAction::NotRejected, conn).await
+    }.scope_boxed()).await?;
+
+    live.notify(e_id);
+    Ok(())
+}
+
+pub async fn get_event_user_by_ids(e_id: i32, u_id: i32, conn: &mut DBConnection) -> APIResult<EventUser> {
+    event_user::table
+        .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::user_id.eq(u_id))
+        .select(EventUser::as_select())
+        .get_result(&mut conn.0)
+        .await
+        .map_err(|_| APIError::UserNotInEvent)
 }
 
 pub fn add_event_user_routes(router: Router<Backend>) -> Router<Backend> {
-    router.route("/event/:event_id/users/:user_id", get(get_event_user))
+    router.route("/event/:event_id/users/admin", get(get_event_users_admin))
+        .route("/event/:event_id/reject/:user_id", post(reject_event_user))
+        .route("/event/:event_id/unreject/:user_id", post(unreject_event_user))
+        .route("/event/:event_id/users/:user_id", get(get_event_user))
         .route("/event/:event_id/users", get(get_event_users))
         .route("/event/:event_id/users/check_attended", get(get_event_users_check_attended))
         .route("/event/:event_id/register/:user_id", post(register_to_event))
         .route("/event/:event_id/unregister/:user_id", post(unregister_from_event))
-        .route("/event/:event_id/change_guests/:user_id", post(change_guests))
+        .route("/event/:event_id/confirm_offer/:user_id", post(confirm_offer))
         .route("/event/:event_id/attended/:user_id", post(set_attended))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use crate::backend::Backend;
+    use crate::events::users::ADMITTED_STATES;
+    use crate::schema::{event, event_guest, user_action, users};
+    use super::*;
+
+    const SLOTS: i32 = 5;
+    const REGISTRATIONS: usize = 30;
+
+    async fn create_user(index: usize, conn: &mut DBConnection) -> i32 {
+        let u_id = diesel::insert_into(users::table)
+            .values((
+                users::email.eq(format!("concurrent-{}-{index}@test.invalid", std::process::id())),
+                users::pw_hash.eq(""),
+                users::email_confirmed.eq(true),
+            ))
+            .returning(users::id)
+            .get_result::<i32>(&mut conn.0)
+            .await
+            .unwrap();
+
+        diesel::insert_into(user_data::table)
+            .values((
+                user_data::user_id.eq(u_id),
+                user_data::name.eq(format!("User {index}")),
+                user_data::fetlife_name.eq(""),
+                user_data::experience_text.eq(""),
+                user_data::found_us_text.eq(""),
+                user_data::goal_text.eq(""),
+                user_data::role_factor.eq(50.0),
+                user_data::open.eq(false),
+                user_data::show_name.eq(false),
+                user_data::show_role.eq(false),
+                user_data::show_open.eq(false),
+            ))
+            .execute(&mut conn.0)
+            .await
+            .unwrap();
+
+        u_id
+    }
+
+    async fn create_event(conn: &mut DBConnection) -> i32 {
+        let now = Local::now().naive_local();
+
+        diesel::insert_into(event::table)
+            .values((
+                event::date.eq(now + Duration::days(7)),
+                event::visible_date.eq(now - Duration::days(1)),
+                event::register_deadline.eq(now + Duration::days(6)),
+                event::archive_date.eq(now + Duration::days(8)),
+                event::slots.eq(SLOTS),
+                event::new_slots.eq(0),
+                event::visible.eq(true),
+                event::archive.eq(false),
+                event::custom_workshop.eq(""),
+                event::workshop_file.eq(""),
+            ))
+            .returning(event::id)
+            .get_result::<i32>(&mut conn.0)
+            .await
+            .unwrap()
+    }
+
+    async fn cleanup(e_id: i32, u_ids: &[i32], conn: &mut DBConnection) {
+        diesel::delete(user_action::table).filter(user_action::event_id.eq(e_id)).execute(&mut conn.0).await.unwrap();
+        diesel::delete(event_guest::table)
+            .filter(event_guest::event_user_id.eq_any(event_user::table.filter(event_user::event_id.eq(e_id)).select(event_user::id)))
+            .execute(&mut conn.0).await.unwrap();
+        diesel::delete(event_user::table).filter(event_user::event_id.eq(e_id)).execute(&mut conn.0).await.unwrap();
+        diesel::delete(event::table).filter(event::id.eq(e_id)).execute(&mut conn.0).await.unwrap();
+        diesel::delete(user_data::table).filter(user_data::user_id.eq_any(u_ids)).execute(&mut conn.0).await.unwrap();
+        diesel::delete(users::table).filter(users::id.eq_any(u_ids)).execute(&mut conn.0).await.unwrap();
+    }
+
+    /// Needs a migrated database in `DATABASE_URL` and is skipped without one.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn parallel_registrations_never_exceed_slots() {
+        if std::env::var("DATABASE_URL").is_err() {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return
+        }
+
+        let backend = Backend::new().await.unwrap();
+        let mut conn = backend.get_connection().await.unwrap();
+
+        let e_id = create_event(&mut conn).await;
+        let mut u_ids = vec![];
+        for index in 0..REGISTRATIONS {
+            u_ids.push(create_user(index, &mut conn).await);
+        }
+
+        let tasks = u_ids.iter().copied()
+            .map(|u_id| {
+                let backend = backend.clone();
+                tokio::spawn(async move {
+                    let mut conn = backend.get_connection().await?;
+                    register_event_user(e_id, u_id, vec![], false, &mut conn).await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        let admitted = event_user::table
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::state.eq_any(ADMITTED_STATES))
+            .count()
+            .get_result::<i64>(&mut conn.0)
+            .await
+            .unwrap();
+
+        let mut waiting_slots = event_user::table
+            .filter(event_user::event_id.eq(e_id))
+            .filter(event_user::state.eq(EventUserState::Waiting))
+            .select(event_user::slot)
+            .get_results::<i32>(&mut conn.0)
+            .await
+            .unwrap();
+        waiting_slots.sort();
+
+        cleanup(e_id, &u_ids, &mut conn).await;
+
+        assert_eq!(admitted, SLOTS as i64);
+        assert_eq!(waiting_slots, (0..(REGISTRATIONS as i32 - SLOTS)).collect::<Vec<_>>());
+    }
+}
@@ -1,10 +1,10 @@
-use diesel::dsl::{count_star, max, sum};
+use diesel::dsl::count_star;
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use crate::backend::DBConnection;
 use crate::error::{APIError, APIResult};
 use crate::events::CUSTOM_WORKSHOP;
-use crate::events::users::{EventUser, EventUserState};
+use crate::events::users::{ADMITTED_STATES, EventUser, EventUserState};
 use crate::markdown_files::{get_file_content};
 use crate::schema::{event, event_user, user_data};
 use crate::markdown_files::WORKSHOP_TEXT_SUB_PATH;
@@ -32,13 +32,36 @@ pub async fn is_user_new(u_id: i32, conn: &mut DBConnection) -> APIResult<bool>
 pub async fn get_slots_and_new_slots_of_event(e_id: i32, conn: &mut DBConnection) -> APIResult<(i32, i32)> {
      event::table
         .filter(event::id.eq(e_id))
-        .filter(event::visible.eq(true))
         .select((event::slots, event::new_slots))
         .get_result(&mut conn.0)
         .await
         .map_err(APIError::internal)
 }
 
+/// Returns how many hours a waiting user has to confirm a freed slot, or `None` if the event admits waiting users directly.
+pub async fn get_offer_hours_of_event(e_id: i32, conn: &mut DBConnection) -> APIResult<Option<i32>> {
+    let (offer_mode, offer_hours) = event::table
+        .filter(event::id.eq(e_id))
+        .select((event::offer_mode, event::offer_hours))
+        .get_result::<(bool, i32)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(if offer_mode { Some(offer_hours) } else { None })
+}
+
+/// In lottery mode registrations go into the pool until the lottery is drawn.
+pub async fn is_lottery_pending(e_id: i32, conn: &mut DBConnection) -> APIResult<bool> {
+    let (lottery_mode, lottery_seed) = event::table
+        .filter(event::id.eq(e_id))
+        .select((event::lottery_mode, event::lottery_seed))
+        .get_result::<(bool, Option<i64>)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(lottery_mode && lottery_seed.is_none())
+}
+
 pub async fn get_slots_and_description_of_event_with_admin_check(e_id: i32, admin: bool, conn: &mut DBConnection) -> APIResult<(i32, i32, String)> {
     let (slots, new_slots, workshop_file, custom_workshop): (i32, i32, String, String) = if admin {
         event::table
@@ -51,6 +74,7 @@ pub async fn get_slots_and_description_of_event_with_admin_check(e_id: i32, admi
         event::table
             .filter(event::id.eq(e_id))
             .filter(event::visible.eq(true))
+            .filter(event::cancelled.eq(false))
             .select((event::slots, event::new_slots, event::workshop_file, event::custom_workshop))
             .get_result(&mut conn.0)
             .await
@@ -69,24 +93,37 @@ pub async fn get_slots_and_description_of_event_with_admin_check(e_id: i32, admi
     Ok((slots, new_slots, description))
 }
 
-pub async fn get_max_slot_index_with_state(e_id: i32, state: EventUserState, conn: &mut DBConnection) -> APIResult<i32> {
-    let max_slot_index = event_user::table
+/// Locks the event row until the end of the current transaction, so slot changes of one event happen one after another.
+pub async fn lock_event(e_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    event::table
+        .filter(event::id.eq(e_id))
+        .select(event::id)
+        .for_update()
+        .get_result::<i32>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(())
+}
+
+/// The waiting indices are kept without gaps, so the next free index is the number of users in the queue.
+pub async fn get_next_slot_index_with_states(e_id: i32, states: &[EventUserState], conn: &mut DBConnection) -> APIResult<i32> {
+    let count = event_user::table
         .filter(event_user::event_id.eq(e_id))
-        .filter(event_user::state.eq(state))
-        .select(max(event_user::slot))
-        .get_result::<Option<i32>>(&mut conn.0)
+        .filter(event_user::state.eq_any(states))
+        .count()
+        .get_result::<i64>(&mut conn.0)
         .await
-        .map_err(APIError::internal)?
-        .unwrap_or_default();
+        .map_err(APIError::internal)?;
 
-    Ok(max_slot_index)
+    Ok(count as i32)
 }
 
 pub async fn get_count_of_event_users_with_state(id: i32, states: &[EventUserState], conn: &mut DBConnection) -> APIResult<i32> {
     let (register_count, guest_count) = event_user::table
         .filter(event_user::event_id.eq(id))
         .filter(event_user::state.eq_any(states))
-        .select((count_star(), sum(event_user::guests)))
+        .select((count_star(), diesel::dsl::sum(event_user::guests)))
         .get_result::<(i64, Option<i64>)>(&mut conn.0)
         .await
         .map_err(APIError::internal)?;
@@ -99,7 +136,7 @@ pub async fn get_count_of_event_users_with_state_expect_user_id(e_id: i32, u_id:
         .filter(event_user::event_id.eq(e_id))
         .filter(event_user::user_id.ne(u_id))
         .filter(event_user::state.eq_any(states))
-        .select((count_star(), sum(event_user::guests)))
+        .select((count_star(), diesel::dsl::sum(event_user::guests)))
         .get_result::<(i64, Option<i64>)>(&mut conn.0)
         .await
         .map_err(APIError::internal)?;
@@ -121,28 +158,63 @@ pub async fn get_count_of_event_users_open_with_state(id: i32, state: &[EventUse
     Ok(open_count as i32)
 }
 
-pub async fn get_next_waiting_event_users(e_id: i32, conn: &mut DBConnection) -> APIResult<EventUser> {
-    event_user::table
+pub async fn get_waiting_event_users_with_role_factor(e_id: i32, new: bool, conn: &mut DBConnection) -> APIResult<Vec<(EventUser, f64)>> {
+    let query = event_user::table
         .filter(event_user::event_id.eq(e_id))
-        .filter(event_user::state.eq_any(&[EventUserState::Waiting, EventUserState::WaitingNew]))
-        .filter(event_user::slot.eq(0))
-        .select(EventUser::as_select())
-        .get_result(&mut conn.0)
+        .inner_join(user_data::table.on(event_user::user_id.eq(user_data::user_id)))
+        .select((EventUser::as_select(), user_data::role_factor))
+        .into_boxed();
+
+    let query = if new {
+        query
+            .filter(event_user::state.eq(EventUserState::WaitingNew))
+            .order(event_user::new_slot.asc())
+    } else {
+        query
+            .filter(event_user::state.eq_any([EventUserState::Waiting, EventUserState::WaitingNew]))
+            .order(event_user::slot.asc())
+    };
+
+    query
+        .get_results(&mut conn.0)
         .await
         .map_err(APIError::internal)
 }
 
-pub async fn get_next_waiting_new_event_users(e_id: i32, conn: &mut DBConnection) -> APIResult<EventUser> {
-    event_user::table
-        .filter(event_user::event_id.eq(e_id))
-        .filter(event_user::state.eq(EventUserState::WaitingNew))
-        .filter(event_user::new_slot.eq(0))
-        .select(EventUser::as_select())
-        .get_result(&mut conn.0)
+/// Returns the target role factor and the tolerance, or `None` if the event does not balance roles.
+pub async fn get_role_balance_of_event(e_id: i32, conn: &mut DBConnection) -> APIResult<Option<(f64, f64)>> {
+    let (balance_roles, target_role_factor, role_tolerance) = event::table
+        .filter(event::id.eq(e_id))
+        .select((event::balance_roles, event::target_role_factor, event::role_tolerance))
+        .get_result::<(bool, f64, f64)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok(if balance_roles { Some((target_role_factor, role_tolerance)) } else { None })
+}
+
+pub async fn get_role_factor_of_user(u_id: i32, conn: &mut DBConnection) -> APIResult<f64> {
+    user_data::table
+        .filter(user_data::user_id.eq(u_id))
+        .select(user_data::role_factor)
+        .get_result::<f64>(&mut conn.0)
         .await
         .map_err(APIError::internal)
 }
 
+pub async fn get_admitted_role_factor_sum_and_count(e_id: i32, conn: &mut DBConnection) -> APIResult<(f64, i64)> {
+    let (role_factor_sum, count) = event_user::table
+        .filter(event_user::event_id.eq(e_id))
+        .filter(event_user::state.eq_any(ADMITTED_STATES))
+        .inner_join(user_data::table.on(event_user::user_id.eq(user_data::user_id)))
+        .select((diesel::dsl::sum(user_data::role_factor), count_star()))
+        .get_result::<(Option<f64>, i64)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok((role_factor_sum.unwrap_or_default(), count))
+}
+
 pub async fn is_event_user_states(e_id: i32, u_id: i32, state: &[EventUserState], conn: &mut DBConnection) -> bool {
     event_user::table
         .filter(event_user::event_id.eq(e_id))
@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+use axum::extract::Path;
+use axum::{Json, Router};
+use axum::routing::get;
+use chrono::{Duration, Local};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use utoipa::ToSchema;
+use crate::auth::AuthSession;
+use crate::auth::util::auth_to_id_is_me_or_i_am_admin;
+use crate::backend::{Backend, DBConnection};
+use crate::config::admission_history_days;
+use crate::error::{APIError, APIResult};
+use crate::events::user_action::EventUserAction;
+use crate::events::users::{get_event_user_by_ids, EventUserState, NEW_STATES, REGISTERED_STATES};
+use crate::events::util::{get_count_of_event_users_with_state, get_slots_and_new_slots_of_event};
+use crate::schema::{event, event_user, user_action};
+
+/// Position of a user in one waiting queue. `seats_ahead` counts the users ahead including their guests.
+/// `admission_rate` is the share of past events in which the same position was admitted, `None` without history.
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct QueuePosition {
+    pub position: i32,
+    pub seats_ahead: i32,
+    pub free_seats: i32,
+    pub admission_rate: Option<f64>,
+    pub history_samples: i32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema, Debug, PartialEq)]
+pub struct WaitingPosition {
+    pub state: EventUserState,
+    pub waiting: QueuePosition,
+    pub new: Option<QueuePosition>,
+}
+
+/// Returns the number of users and seats ahead in the queue.
+async fn get_ahead_in_queue(e_id: i32, slot: i32, new: bool, conn: &mut DBConnection) -> APIResult<(i32, i32)> {
+    let query = event_user::table
+        .filter(event_user::event_id.eq(e_id))
+        .select((count_star(), diesel::dsl::sum(event_user::guests)))
+        .into_boxed();
+
+    let query = if new {
+        query
+            .filter(event_user::state.eq(EventUserState::WaitingNew))
+            .filter(event_user::new_slot.lt(slot))
+    } else {
+        query
+            .filter(event_user::state.eq_any([EventUserState::Waiting, EventUserState::WaitingNew]))
+            .filter(event_user::slot.lt(slot))
+    };
+
+    let (users, guests) = query
+        .get_result::<(i64, Option<i64>)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    Ok((users as i32, (users + guests.unwrap_or_default()) as i32))
+}
+
+/// The waiting lists of past events in registration order, with the users that got a slot later.
+/// Positions are taken from the order of the registrations that went to the waiting list,
+/// users leaving the list in between are not considered, so this is only a rough history.
+struct QueueHistory {
+    waiting: Vec<Vec<i32>>,
+    new: Vec<Vec<i32>>,
+    admitted: Vec<HashSet<i32>>,
+}
+
+async fn get_queue_history(conn: &mut DBConnection) -> APIResult<QueueHistory> {
+    let now = Local::now().naive_local();
+    let since = now - Duration::days(admission_history_days());
+
+    let actions = user_action::table
+        .inner_join(event::table)
+        .filter(event::date.lt(now))
+        .filter(event::date.ge(since))
+        .filter(event::cancelled.eq(false))
+        .filter(user_action::action.eq_any([EventUserAction::Register, EventUserAction::GetSlot, EventUserAction::Offered]))
+        .order(user_action::date.asc())
+        .select((user_action::event_id, user_action::user_id, user_action::action, user_action::in_waiting, user_action::in_new))
+        .get_results::<(i32, i32, EventUserAction, bool, bool)>(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    let mut events = HashMap::<i32, (Vec<i32>, Vec<i32>, HashSet<i32>)>::new();
+    for (e_id, u_id, action, in_waiting, in_new) in actions {
+        let (waiting, new, admitted) = events.entry(e_id).or_default();
+        match action {
+            EventUserAction::Register if in_waiting => {
+                waiting.push(u_id);
+                if in_new {
+                    new.push(u_id);
+                }
+            }
+            EventUserAction::GetSlot | EventUserAction::Offered => {
+                admitted.insert(u_id);
+            }
+            _ => {}
+        }
+    }
+
+    let mut history = QueueHistory { waiting: vec![], new: vec![], admitted: vec![] };
+    for (waiting, new, admitted) in events.into_values() {
+        history.waiting.push(waiting);
+        history.new.push(new);
+        history.admitted.push(admitted);
+    }
+
+    Ok(history)
+}
+
+/// Share of past queues that were at least `position` long in which the user at `position` was admitted.
+fn get_admission_rate(queues: &[Vec<i32>], admitted: &[HashSet<i32>], position: i32) -> (Option<f64>, i32) {
+    let index = (position - 1) as usize;
+
+    let (samples, hits) = queues.iter()
+        .zip(admitted)
+        .filter_map(|(queue, admitted)| queue.get(index).map(|u_id| admitted.contains(u_id)))
+        .fold((0, 0), |(samples, hits), hit| (samples + 1, hits + hit as i32));
+
+    let rate = if samples > 0 { Some(hits as f64 / samples as f64) } else { None };
+    (rate, samples)
+}
+
+#[utoipa::path(
+    get,
+    path = "/event/{event_id}/waiting_position/{user_id}"
+)]
+pub async fn get_waiting_position(
+    auth: AuthSession,
+    Path((e_id, u_id)): Path<(i32, i32)>,
+) -> APIResult<Json<WaitingPosition>> {
+    let mut conn = auth_to_id_is_me_or_i_am_admin(auth, u_id).await?;
+
+    let event_user = get_event_user_by_ids(e_id, u_id, &mut conn).await?;
+    if event_user.state != EventUserState::Waiting && event_user.state != EventUserState::WaitingNew {
+        return Err(APIError::UserNotWaiting)
+    }
+
+    let (slots, new_slots) = get_slots_and_new_slots_of_event(e_id, &mut conn).await?;
+    let history = get_queue_history(&mut conn).await?;
+
+    let (users_ahead, seats_ahead) = get_ahead_in_queue(e_id, event_user.slot, false, &mut conn).await?;
+    let register_count = get_count_of_event_users_with_state(e_id, &REGISTERED_STATES, &mut conn).await?;
+    let (admission_rate, history_samples) = get_admission_rate(&history.waiting, &history.admitted, users_ahead + 1);
+    let waiting = QueuePosition {
+        position: users_ahead + 1,
+        seats_ahead,
+        free_seats: (slots - register_count).max(0),
+        admission_rate,
+        history_samples,
+    };
+
+    let new = if event_user.state == EventUserState::WaitingNew {
+        let (users_ahead, seats_ahead) = get_ahead_in_queue(e_id, event_user.new_slot, true, &mut conn).await?;
+        let new_count = get_count_of_event_users_with_state(e_id, &NEW_STATES, &mut conn).await?;
+        let (admission_rate, history_samples) = get_admission_rate(&history.new, &history.admitted, users_ahead + 1);
+        Some(QueuePosition {
+            position: users_ahead + 1,
+            seats_ahead,
+            free_seats: (new_slots - new_count).max(0),
+            admission_rate,
+            history_samples,
+        })
+    } else {
+        None
+    };
+
+    Ok(Json(WaitingPosition {
+        state: event_user.state,
+        waiting,
+        new,
+    }))
+}
+
+pub fn add_waiting_position_routes(router: Router<Backend>) -> Router<Backend> {
+    router.route("/event/:event_id/waiting_position/:user_id", get(get_waiting_position))
+}
@@ -1,7 +1,10 @@
 use mail_send::mail_builder::MessageBuilder;
+use chrono::NaiveDateTime;
 use mail_send::SmtpClientBuilder;
 use crate::error::APIResult;
-use crate::markdown_files::{expect_content_populated, get_file_content, get_mail_file_meta_data, populate_mail_file_with_url, populate_mail_file_with_user_data};
+use crate::events::public::EventDate;
+use crate::events::users::EventUser;
+use crate::markdown_files::{expect_content_populated, get_file_content, get_mail_file_meta_data, populate_mail_file_with_date, populate_mail_file_with_email, populate_mail_file_with_event_data, populate_mail_file_with_event_user, populate_mail_file_with_url, populate_mail_file_with_user_data};
 use crate::user_data::UserData;
 
 pub async fn send_mail(to_name: &str, to_mail: &str, subject: &str, content: &str) -> APIResult<()> {
@@ -38,5 +41,71 @@ pub async fn send_password_reset_mail(email: &str, user_data: UserData, url: &st
     
     send_mail(&user_data.name, email, &meta.title, &content).await?;
     
+    Ok(())
+}
+
+pub async fn send_email_verification_mail(email: &str, url: &str) -> APIResult<()> {
+    let content = get_file_content("/mails/verify_email.md")?;
+    let (meta, content) = get_mail_file_meta_data(content)?;
+
+    let content = populate_mail_file_with_email(content, email);
+    let content = populate_mail_file_with_url(content, url);
+    expect_content_populated(&content)?;
+
+    send_mail(email, email, &meta.title, &content).await?;
+
+    Ok(())
+}
+
+pub async fn send_email_change_mail(new_email: &str, url: &str) -> APIResult<()> {
+    let content = get_file_content("/mails/change_email.md")?;
+    let (meta, content) = get_mail_file_meta_data(content)?;
+
+    let content = populate_mail_file_with_email(content, new_email);
+    let content = populate_mail_file_with_url(content, url);
+    expect_content_populated(&content)?;
+
+    send_mail(new_email, new_email, &meta.title, &content).await?;
+
+    Ok(())
+}
+
+/// Tells the old address about a requested change, so a hijacked account does not go unnoticed.
+pub async fn send_email_change_notice_mail(old_email: &str, new_email: &str) -> APIResult<()> {
+    let content = get_file_content("/mails/change_email_notice.md")?;
+    let (meta, content) = get_mail_file_meta_data(content)?;
+
+    let content = populate_mail_file_with_email(content, new_email);
+    expect_content_populated(&content)?;
+
+    send_mail(old_email, old_email, &meta.title, &content).await?;
+
+    Ok(())
+}
+
+pub async fn send_account_locked_mail(email: &str, locked_until: &NaiveDateTime) -> APIResult<()> {
+    let content = get_file_content("/mails/account_locked.md")?;
+    let (meta, content) = get_mail_file_meta_data(content)?;
+
+    let content = populate_mail_file_with_email(content, email);
+    let content = populate_mail_file_with_date(content, locked_until);
+    expect_content_populated(&content)?;
+
+    send_mail(email, email, &meta.title, &content).await?;
+
+    Ok(())
+}
+
+pub async fn send_event_cancelled_mail(email: &str, user_data: UserData, event_date: &EventDate, event_user: &EventUser) -> APIResult<()> {
+    let content = get_file_content("/mails/event_cancelled.md")?;
+    let (meta, content) = get_mail_file_meta_data(content)?;
+
+    let content = populate_mail_file_with_user_data(content, &user_data);
+    let content = populate_mail_file_with_event_data(content, event_date);
+    let content = populate_mail_file_with_event_user(content, event_user);
+    expect_content_populated(&content)?;
+
+    send_mail(&user_data.name, email, &meta.title, &content).await?;
+
     Ok(())
 }
\ No newline at end of file
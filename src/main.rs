@@ -11,30 +11,49 @@ pub mod cors;
 pub mod firebase;
 pub mod mails;
 pub mod markdown_files;
+pub mod config;
+pub mod session_store;
 
 use std::fmt::Debug;
 use axum::{
+    middleware,
     Router,
     routing::get,
 };
 use std::net::SocketAddr;
 use axum_login::{AuthManagerLayerBuilder, permission_required};
-use axum_login::tower_sessions::{MemoryStore, SessionManagerLayer};
 use tracing::info;
 
 
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::auth::routes::{add_admin_auth_routes, add_auth_routes};
+use crate::auth::password_reset::add_password_reset_routes;
+use crate::auth::email_verification::add_email_verification_routes;
+use crate::auth::account::add_account_routes;
+use crate::auth::login_protection::add_admin_login_protection_routes;
+use crate::auth::api_tokens::{add_api_token_routes, api_token_auth};
+use crate::auth::two_factor::{add_admin_two_factor_routes, add_two_factor_routes};
 use crate::backend::Backend;
 use crate::cors::add_cors_layer;
-use crate::events::{add_admin_event_routes};
-use crate::events::users::{add_admin_event_user_routes, add_event_user_routes};
+use crate::events::{add_admin_event_routes, add_event_organizer_routes};
+use crate::events::users::add_event_user_routes;
 use crate::events::public::add_public_event_routes;
+use crate::events::guests::add_event_guest_routes;
+use crate::events::series::add_admin_event_series_routes;
+use crate::events::scheduler::{add_admin_scheduler_routes, start_event_scheduler};
 use crate::events::user_action::add_user_action_routes;
+use crate::events::calendar::add_calendar_routes;
+use crate::events::check_in::add_check_in_routes;
+use crate::events::reliability::add_admin_reliability_routes;
+use crate::events::graduation::add_admin_graduation_routes;
+use crate::events::lottery::add_lottery_routes;
+use crate::events::waiting::add_waiting_position_routes;
+use crate::events::live::add_live_routes;
 use crate::mails::{send_mail, send_password_reset_mail};
 use crate::markdown_files::routes::add_admin_markdown_files_routes;
 use crate::open_api::add_swagger_route;
+use crate::session_store::{session_layer, start_session_cleanup, PostgresSessionStore};
 use crate::permissions::{UserPermission};
 use crate::permissions::routes::{add_admin_permission_routes, add_permission_routes};
 use crate::user_data::{add_admin_user_data_routes, add_user_data_routes, UserData};
@@ -70,32 +89,53 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let session_store = MemoryStore::default();
-    let session_layer = SessionManagerLayer::new(session_store);
+    let backend = Backend::new().await.unwrap();
+    tokio::spawn(start_event_scheduler(backend.clone()));
 
+    let session_store = PostgresSessionStore::new(backend.db_pool.clone());
+    tokio::spawn(start_session_cleanup(session_store.clone()));
+    let session_layer = session_layer(session_store);
 
-    let backend = Backend::new().await.unwrap();
     let auth_layer = AuthManagerLayerBuilder::new(backend.clone(), session_layer).build();
     
     let mut router = Router::<Backend>::new();
     
     router = add_admin_auth_routes(router);
+    router = add_admin_login_protection_routes(router);
+    router = add_admin_two_factor_routes(router);
     router = add_admin_permission_routes(router);
     router = add_admin_event_routes(router);
     router = add_admin_user_data_routes(router);
-    router = add_admin_event_user_routes(router);
     router = add_admin_markdown_files_routes(router);
+    router = add_admin_scheduler_routes(router);
+    router = add_admin_event_series_routes(router);
+    router = add_admin_reliability_routes(router);
+    router = add_admin_graduation_routes(router);
     router = router.route_layer(permission_required!(Backend, UserPermission::Admin));
 
     router = add_swagger_route(router);
     router = add_auth_routes(router);
+    router = add_password_reset_routes(router);
+    router = add_email_verification_routes(router);
+    router = add_account_routes(router);
+    router = add_two_factor_routes(router);
+    router = add_api_token_routes(router);
     router = add_user_data_routes(router);
     router = add_permission_routes(router);
+    router = add_event_organizer_routes(router);
     router = add_event_user_routes(router);
+    router = add_lottery_routes(router);
+    router = add_waiting_position_routes(router);
+    router = add_live_routes(router);
+    router = add_event_guest_routes(router);
+    router = add_check_in_routes(router);
     router = add_public_event_routes(router);
+    router = add_calendar_routes(router);
     router = add_user_action_routes(router);
     
     router = router.route("/", get(|| async { "This is the Rope Lab Website Backend" }));
+    // Runs inside the auth layer, so bearer tokens can set the user of the auth session.
+    router = router.layer(middleware::from_fn(api_token_auth));
     router = router.layer(auth_layer);
     router = add_cors_layer(router);
 
@@ -108,6 +148,6 @@ async fn main() {
     
     println!("API Dashboard at: https://localhost:3001/swagger-ui");
     //open::that("localhost:3001/swagger-ui").unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
 
@@ -2,6 +2,7 @@ pub mod routes;
 
 use std::collections::HashMap;
 use std::fs;
+use chrono::NaiveDateTime;
 use markdown_meta_parser::MetaData;
 use crate::error::{APIError, APIResult};
 use crate::events::public::EventDate;
@@ -55,26 +56,39 @@ pub fn populate_mail_file_with_event_data(mut content: String, event_data: &Even
         .replace("{Event Time}", &event_data.date.format("%H:%M").to_string())
 }
 
+pub fn get_event_user_state_text(state: EventUserState) -> &'static str {
+    match state {
+        EventUserState::Registered => {"Zum Event angenommen"}
+        EventUserState::Waiting => {"Warteliste"}
+        EventUserState::Rejected => {"Abgelent"}
+        EventUserState::New => {"Platz als Neuling"}
+        EventUserState::WaitingNew => {"Warteliste als Neuling"}
+        EventUserState::Offered => {"Platz angeboten"}
+        EventUserState::OfferedNew => {"Platz als Neuling angeboten"}
+        EventUserState::Lottery => {"Verlosung"}
+        EventUserState::Cancelled => {"Event abgesagt"}
+    }
+}
+
 pub fn populate_mail_file_with_event_user(mut content: String, event_user: &EventUser) -> String {
-    let slots = if event_user.state == EventUserState::New || event_user.state == EventUserState::WaitingNew
+    let slots = if event_user.state == EventUserState::New || event_user.state == EventUserState::WaitingNew || event_user.state == EventUserState::OfferedNew
     {
         event_user.new_slot.to_string()
     }  else {
         event_user.slot.to_string()
     };
 
-    content.replace("{Event User State}", match event_user.state {
-        EventUserState::Registered => {"Zum Event angenommen"}
-        EventUserState::Waiting => {"Warteliste"}
-        EventUserState::Rejected => {"Abgelent"}
-        EventUserState::New => {"Platz als Neuling"}
-        EventUserState::WaitingNew => {"Warteliste als Neuling"}
-    })
+    content.replace("{Event User State}", get_event_user_state_text(event_user.state))
         .replace("{Guests}", &event_user.guests.to_string())
         .replace("{Attended}", &event_user.attended.to_string())
         .replace("{Slot}", &slots)
 }
 
+pub fn populate_mail_file_with_date(mut content: String, date: &NaiveDateTime) -> String {
+    content.replace("{Date}", &date.format("%d.%m.%Y").to_string())
+        .replace("{Time}", &date.format("%H:%M").to_string())
+}
+
 pub fn populate_mail_file_with_url(mut content: String, url: &str) -> String {
     content.replace("{URL}", url)
 }
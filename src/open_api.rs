@@ -5,6 +5,12 @@ use utoipa_swagger_ui::SwaggerUi;
 use crate::backend::Backend;
 use crate::auth::*;
 use crate::auth::routes::*;
+use crate::auth::password_reset::*;
+use crate::auth::email_verification::*;
+use crate::auth::account::*;
+use crate::auth::login_protection::*;
+use crate::auth::two_factor::*;
+use crate::auth::api_tokens::*;
 use crate::user_data::*;
 use crate::permissions::*;
 use crate::permissions::routes::*;
@@ -12,6 +18,16 @@ use crate::events::*;
 use crate::events::users::*;
 use crate::events::user_action::*;
 use crate::events::public::*;
+use crate::events::guests::*;
+use crate::events::series::*;
+use crate::events::scheduler::*;
+use crate::events::calendar::*;
+use crate::events::check_in::*;
+use crate::events::reliability::*;
+use crate::events::graduation::*;
+use crate::events::lottery::*;
+use crate::events::waiting::*;
+use crate::events::live::*;
 
 #[derive(OpenApi)]
 #[openapi(
@@ -20,46 +36,119 @@ use crate::events::public::*;
         sign_up,
         login,
         logout,
+        forgot_password,
+        reset_password,
+        verify_email,
+        resend_verification_email,
+        change_password,
+        change_email,
+        confirm_email_change,
+        unlock_user,
+        login_two_factor,
+        get_two_factor_status,
+        enroll_two_factor,
+        enable_two_factor,
+        disable_two_factor,
+        regenerate_recovery_codes,
+        reset_two_factor,
+        create_api_token,
+        get_api_tokens,
+        revoke_api_token,
         get_id,
         get_email,
         get_all_users,
+        get_user_reliability,
+        get_reliability_all,
         get_user_data,
         post_user_data,
         get_user_data_all,
+        post_graduate_new_users,
         post_event,
         update_event,
         delete_event,
+        cancel_event,
         get_event,
         get_event_all,
         get_event_user,
         get_event_users,
         register_to_event,
         unregister_from_event,
-        change_guests,
+        get_guests,
+        add_guest,
+        remove_guest,
+        set_guest_attended,
+        confirm_offer,
+        get_waiting_position,
+        get_check_in_token,
+        check_in,
+        reject_event_user,
+        unreject_event_user,
         get_event_dates,
         get_event_public_data,
         get_event_logged_in_data,
+        get_event_live,
+        get_public_calendar,
+        get_personal_calendar,
+        get_calendar_token,
+        reset_calendar_token,
         get_user_actions,
+        post_run_event_schedule,
+        post_draw_lottery,
+        post_event_series,
+        update_event_series,
+        get_event_series_all,
+        cancel_series_occurrence,
         get_permissions,
         post_permission_has,
         post_permission_add,
         post_permission_remove, 
+        post_event_permission_add,
+        post_event_permission_remove,
+        get_event_permissions,
     ), 
     components(schemas(
         User,
         Credentials,
+        ForgotPassword,
+        ResetPassword,
+        VerifyEmail,
+        ChangePassword,
+        ChangeEmail,
+        ConfirmEmailChange,
+        TwoFactorStatus,
+        TwoFactorEnrollment,
+        TwoFactorCode,
+        TwoFactorPassword,
+        DisableTwoFactor,
+        NewApiToken,
+        CreatedApiToken,
+        ApiToken,
         UserData,
         UserPermission,
         Permission,
+        EventPermissionType,
+        EventPermission,
         Event,
         NewEvent,
         EventUser,
+        EventGuest,
+        CheckIn,
+        Reliability,
+        LotteryResult,
+        QueuePosition,
+        WaitingPosition,
+        LiveEventData,
+        NewEventGuest,
         PublicEventUser,
         PublicEventUserLists,
         EventDate,
         PublicEventData,
         LoggedInEventData,
         UserAction,
+        ScheduleResult,
+        CapacityChange,
+        EventSeries,
+        NewEventSeries,
     )))]
 struct ApiDoc;
 
@@ -7,9 +7,12 @@ use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use utoipa::{ToSchema};
+use crate::auth::api_tokens::api_token_scope;
+use crate::auth::two_factor::has_two_factor;
 use crate::backend::{Backend, DBConnection};
+use crate::config::two_factor_required_for_staff;
 use crate::error::APIError;
-use crate::schema::{permission};
+use crate::schema::{event_permission, permission};
 use crate::schema::permission::{user_id, user_permission};
 use crate::error::APIResult;
 
@@ -30,7 +33,32 @@ pub struct Permission {
     pub user_permission: UserPermission,
 }
 
-pub async fn has_permission(
+/// Permissions that only apply to a single event. An organizer can also check attendance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize_repr, Deserialize_repr, ToSchema)]
+#[derive(diesel_derive_enum::DbEnum)]
+#[ExistingTypePath = "crate::schema::sql_types::Eventpermissiontype"]
+#[repr(u8)]
+pub enum EventPermissionType {
+    Organizer,
+    CheckAttended,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Queryable, Selectable, Insertable, ToSchema, Debug)]
+#[diesel(table_name = event_permission)]
+pub struct EventPermission {
+    pub user_id: i32,
+    pub event_id: i32,
+    pub permission_type: EventPermissionType,
+}
+
+/// Staff permissions can be set to need two-factor authentication. Without it they have no effect,
+/// but the user can still log in to set it up.
+fn needs_two_factor(perm: UserPermission) -> bool {
+    two_factor_required_for_staff() && (perm == UserPermission::Admin || perm == UserPermission::CheckAttended)
+}
+
+/// Whether the permission was granted, regardless of whether it currently takes effect.
+pub async fn has_granted_permission(
     conn: &mut DBConnection,
     id: UserId<Backend>,
     perm: UserPermission,
@@ -46,6 +74,25 @@ pub async fn has_permission(
     found
 }
 
+/// Requests with an API token can only use the permissions of the token.
+pub async fn has_permission(
+    conn: &mut DBConnection,
+    id: UserId<Backend>,
+    perm: UserPermission,
+) -> bool {
+    if api_token_scope().is_some_and(|scope| !scope.contains(&perm)) {
+        return false
+    }
+
+    let found = has_granted_permission(conn, id, perm).await;
+
+    if found && needs_two_factor(perm) {
+        return has_two_factor(id, conn).await
+    }
+
+    found
+}
+
 pub async fn is_admin(
     conn: &mut DBConnection,
     id: UserId<Backend>,
@@ -67,10 +114,82 @@ pub async fn is_check_attended(
     has_permission(conn, id, UserPermission::CheckAttended).await
 }
 
+pub async fn has_event_permission(
+    conn: &mut DBConnection,
+    id: UserId<Backend>,
+    e_id: i32,
+    perm: EventPermissionType,
+) -> bool {
+    // Event permissions are not part of the permissions an API token can carry.
+    if api_token_scope().is_some() {
+        return false
+    }
+
+    let perms = if perm == EventPermissionType::CheckAttended {
+        vec![EventPermissionType::CheckAttended, EventPermissionType::Organizer]
+    } else {
+        vec![perm]
+    };
+
+    let found = event_permission::table
+        .filter(event_permission::user_id.eq(id))
+        .filter(event_permission::event_id.eq(e_id))
+        .filter(event_permission::permission_type.eq_any(perms))
+        .count()
+        .get_result::<i64>(&mut conn.0)
+        .await
+        .is_ok_and(|count| count > 0);
+
+    // Organizers and door volunteers are staff for their event, so they need two-factor like global staff.
+    if found && two_factor_required_for_staff() {
+        return has_two_factor(id, conn).await
+    }
+
+    found
+}
+
+pub async fn get_event_permissions_of_user(
+    conn: &mut DBConnection,
+    id: UserId<Backend>,
+) -> APIResult<Vec<EventPermission>> {
+    event_permission::table
+        .filter(event_permission::user_id.eq(id))
+        .select(EventPermission::as_select())
+        .get_results(&mut conn.0)
+        .await
+        .map_err(APIError::internal)
+}
+
+pub async fn delete_event_permissions_of_event(
+    conn: &mut DBConnection,
+    e_id: i32,
+) -> APIResult<()> {
+    diesel::delete(event_permission::table)
+        .filter(event_permission::event_id.eq(e_id))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    Ok(())
+}
+
+pub async fn delete_event_permissions_of_user(
+    conn: &mut DBConnection,
+    id: UserId<Backend>,
+) -> APIResult<()> {
+    diesel::delete(event_permission::table)
+        .filter(event_permission::user_id.eq(id))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    Ok(())
+}
+
 pub async fn get_permissions_iter(
     conn: &mut DBConnection,
     id: UserId<Backend>,
 ) -> APIResult<impl Iterator<Item = UserPermission>> {
+    let two_factor = has_two_factor(id, conn).await;
+    let scope = api_token_scope();
     let permissions = permission::table
         .filter(user_id.eq(id))
         .select(Permission::as_select())
@@ -78,7 +197,9 @@ pub async fn get_permissions_iter(
         .await
         .map_err(APIError::internal)?
         .into_iter()
-        .map(|p| {p.user_permission});
+        .map(|p| {p.user_permission})
+        .filter(move |p| two_factor || !needs_two_factor(*p))
+        .filter(move |p| scope.as_ref().is_none_or(|scope| scope.contains(p)));
 
     Ok(permissions)
 }
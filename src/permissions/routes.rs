@@ -6,8 +6,8 @@ use diesel_async::RunQueryDsl;
 use crate::auth::{AuthSession};
 use crate::backend::{Backend, DBConnection};
 use crate::error::{APIError, APIResult};
-use crate::permissions::{get_permissions_iter, has_permission, Permission, UserPermission};
-use crate::schema::permission;
+use crate::permissions::{get_event_permissions_of_user, get_permissions_iter, has_granted_permission, has_permission, EventPermission, EventPermissionType, Permission, UserPermission};
+use crate::schema::{event_permission, permission};
 use crate::auth::util::auth_and_path_to_id_is_me_or_i_am_admin;
 
 #[utoipa::path(
@@ -20,7 +20,7 @@ pub async fn post_permission_add(
     Json(permission): Json<UserPermission>
 ) -> APIResult<()> {
 
-    if has_permission(&mut conn, u_id, permission).await {
+    if has_granted_permission(&mut conn, u_id, permission).await {
         return Err(APIError::PermissionAlreadyAdded)
     }
     
@@ -48,7 +48,7 @@ pub async fn post_permission_remove(
     Json(permission): Json<UserPermission>
 ) -> APIResult<()> {
 
-    if !has_permission(&mut conn, u_id, permission).await {
+    if !has_granted_permission(&mut conn, u_id, permission).await {
         return Err(APIError::PermissionNotThere)
     }
 
@@ -93,12 +93,82 @@ pub async fn get_permissions(
 }
 
 
+#[utoipa::path(
+    post,
+    path = "/permissions/{user_id}/event/{event_id}/add"
+)]
+pub async fn post_event_permission_add(
+    mut conn: DBConnection,
+    Path((u_id, e_id)): Path<(i32, i32)>,
+    Json(permission): Json<EventPermissionType>
+) -> APIResult<()> {
+    let event_permission = EventPermission {
+        user_id: u_id,
+        event_id: e_id,
+        permission_type: permission,
+    };
+
+    let added = diesel::insert_into(event_permission::table)
+        .values(&event_permission)
+        .on_conflict_do_nothing()
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    if added == 0 {
+        return Err(APIError::PermissionAlreadyAdded)
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/permissions/{user_id}/event/{event_id}/remove"
+)]
+pub async fn post_event_permission_remove(
+    mut conn: DBConnection,
+    Path((u_id, e_id)): Path<(i32, i32)>,
+    Json(permission): Json<EventPermissionType>
+) -> APIResult<()> {
+    let removed = diesel::delete(event_permission::table)
+        .filter(event_permission::user_id.eq(u_id))
+        .filter(event_permission::event_id.eq(e_id))
+        .filter(event_permission::permission_type.eq(permission))
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+
+    if removed == 0 {
+        return Err(APIError::PermissionNotThere)
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/permissions/{user_id}/event"
+)]
+pub async fn get_event_permissions(
+    auth_session: AuthSession,
+    Path(u_id): Path<i32>,
+) -> APIResult<Json<Vec<EventPermission>>> {
+    let mut conn = auth_and_path_to_id_is_me_or_i_am_admin(auth_session, u_id).await?;
+
+    let permissions = get_event_permissions_of_user(&mut conn, u_id).await?;
+    Ok(Json(permissions))
+}
+
 pub fn add_admin_permission_routes(router: Router<Backend>) -> Router<Backend> {
     router.route("/permissions/:id/add", post(post_permission_add))
         .route("/permissions/:id/remove", post(post_permission_remove))
+        .route("/permissions/:id/event/:event_id/add", post(post_event_permission_add))
+        .route("/permissions/:id/event/:event_id/remove", post(post_event_permission_remove))
 }
 
 pub fn add_permission_routes(router: Router<Backend>) -> Router<Backend> {
     router.route("/permissions/:id/has", post(post_permission_has))
         .route("/permissions/:id", get(get_permissions))
+        .route("/permissions/:id/event", get(get_event_permissions))
 }
\ No newline at end of file
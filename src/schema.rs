@@ -1,6 +1,14 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "accounttokenkind"))]
+    pub struct Accounttokenkind;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "eventpermissiontype"))]
+    pub struct Eventpermissiontype;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "eventuseraction"))]
     pub struct Eventuseraction;
@@ -14,6 +22,50 @@ pub mod sql_types {
     pub struct Userpermission;
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Accounttokenkind;
+
+    account_token (id) {
+        id -> Int4,
+        user_id -> Int4,
+        kind -> Accounttokenkind,
+        token_hash -> Text,
+        expires -> Timestamp,
+        data -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    api_token (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Text,
+        token_hash -> Text,
+        created -> Timestamp,
+        expires -> Nullable<Timestamp>,
+        last_used -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Userpermission;
+
+    api_token_permission (id) {
+        id -> Int4,
+        token_id -> Int4,
+        user_permission -> Userpermission,
+    }
+}
+
+diesel::table! {
+    calendar_token (user_id) {
+        user_id -> Int4,
+        token_hash -> Text,
+    }
+}
+
 diesel::table! {
     event (id) {
         id -> Int4,
@@ -27,6 +79,65 @@ diesel::table! {
         custom_workshop -> Text,
         new_slots -> Int4,
         workshop_file -> Text,
+        offer_mode -> Bool,
+        offer_hours -> Int4,
+        balance_roles -> Bool,
+        target_role_factor -> Float8,
+        role_tolerance -> Float8,
+        series_id -> Nullable<Int4>,
+        occurrence_date -> Nullable<Timestamp>,
+        cancelled -> Bool,
+        lottery_mode -> Bool,
+        lottery_loser_weight -> Float8,
+        lottery_seed -> Nullable<Int8>,
+        lottery_drawn -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Eventpermissiontype;
+
+    event_permission (id) {
+        id -> Int4,
+        user_id -> Int4,
+        event_id -> Int4,
+        permission_type -> Eventpermissiontype,
+    }
+}
+
+diesel::table! {
+    event_series (id) {
+        id -> Int4,
+        first_date -> Timestamp,
+        end_date -> Nullable<Timestamp>,
+        interval_days -> Int4,
+        generate_days_ahead -> Int4,
+        visible_offset_hours -> Int4,
+        register_deadline_offset_hours -> Int4,
+        archive_offset_hours -> Int4,
+        slots -> Int4,
+        new_slots -> Int4,
+        custom_workshop -> Text,
+        workshop_file -> Text,
+    }
+}
+
+diesel::table! {
+    event_series_exception (id) {
+        id -> Int4,
+        series_id -> Int4,
+        date -> Timestamp,
+    }
+}
+
+diesel::table! {
+    event_guest (id) {
+        id -> Int4,
+        event_user_id -> Int4,
+        name -> Text,
+        user_id -> Nullable<Int4>,
+        attended -> Bool,
     }
 }
 
@@ -43,6 +154,16 @@ diesel::table! {
         guests -> Int4,
         attended -> Bool,
         new_slot -> Int4,
+        offer_expires -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    login_failure (id) {
+        id -> Int4,
+        email -> Text,
+        address -> Text,
+        date -> Timestamp,
     }
 }
 
@@ -57,6 +178,22 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    session (id) {
+        id -> Text,
+        data -> Text,
+        expiry_date -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    totp_recovery_code (id) {
+        id -> Int4,
+        user_id -> Int4,
+        code_hash -> Text,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::Eventuseraction;
@@ -92,24 +229,59 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_totp (user_id) {
+        user_id -> Int4,
+        secret -> Text,
+        enabled -> Bool,
+        last_step -> Nullable<Int8>,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Int4,
         email -> Text,
         pw_hash -> Text,
+        email_confirmed -> Bool,
+        locked_until -> Nullable<Timestamp>,
     }
 }
 
+diesel::joinable!(account_token -> users (user_id));
+diesel::joinable!(api_token -> users (user_id));
+diesel::joinable!(api_token_permission -> api_token (token_id));
+diesel::joinable!(calendar_token -> users (user_id));
+diesel::joinable!(event -> event_series (series_id));
+diesel::joinable!(event_guest -> event_user (event_user_id));
+diesel::joinable!(event_permission -> event (event_id));
+diesel::joinable!(event_permission -> users (user_id));
+diesel::joinable!(event_series_exception -> event_series (series_id));
+diesel::joinable!(event_guest -> users (user_id));
 diesel::joinable!(permission -> users (user_id));
+diesel::joinable!(totp_recovery_code -> users (user_id));
+diesel::joinable!(user_totp -> users (user_id));
 diesel::joinable!(user_action -> event (event_id));
 diesel::joinable!(user_action -> users (user_id));
 diesel::joinable!(user_data -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    account_token,
+    api_token,
+    api_token_permission,
+    calendar_token,
     event,
+    event_guest,
+    event_permission,
+    event_series,
+    event_series_exception,
     event_user,
+    login_failure,
     permission,
+    session,
+    totp_recovery_code,
     user_action,
     user_data,
+    user_totp,
     users,
 );
@@ -0,0 +1,277 @@
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
+use async_trait::async_trait;
+use axum_login::tower_sessions::{Expiry, SessionManagerLayer, SessionStore};
+use axum_login::tower_sessions::session::{Id, Record};
+use axum_login::tower_sessions::session_store;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use diesel::upsert::excluded;
+use diesel_async::RunQueryDsl;
+use tracing::warn;
+use crate::backend::{DBConnection, DBPool};
+use crate::config::{session_cleanup_interval_secs, session_cookie_name, session_expiry_days, session_same_site, session_secure};
+use crate::error::{APIError, APIResult};
+use crate::schema::session;
+
+/// Keeps the sessions in the database, so logins survive restarts and can be shared between instances.
+/// The whole record is stored as JSON, the expiry is kept in its own column for loading and cleanup.
+#[derive(Clone)]
+pub struct PostgresSessionStore {
+    db_pool: DBPool,
+}
+
+impl Debug for PostgresSessionStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostgresSessionStore").finish()
+    }
+}
+
+fn backend_error(err: impl ToString) -> session_store::Error {
+    session_store::Error::Backend(err.to_string())
+}
+
+fn record_to_row(record: &Record) -> session_store::Result<(String, String, DateTime<Utc>)> {
+    let data = serde_json::to_string(record)
+        .map_err(|err| session_store::Error::Encode(err.to_string()))?;
+    let expiry_date = DateTime::from_timestamp(record.expiry_date.unix_timestamp(), record.expiry_date.nanosecond())
+        .ok_or_else(|| session_store::Error::Encode("Session expiry out of range".to_string()))?;
+
+    Ok((record.id.to_string(), data, expiry_date))
+}
+
+impl PostgresSessionStore {
+    pub fn new(db_pool: DBPool) -> Self {
+        PostgresSessionStore { db_pool }
+    }
+
+    pub async fn delete_expired(&self) -> session_store::Result<usize> {
+        let mut conn = self.db_pool.get().await.map_err(backend_error)?;
+
+        diesel::delete(session::table)
+            .filter(session::expiry_date.lt(Utc::now()))
+            .execute(&mut conn)
+            .await
+            .map_err(backend_error)
+    }
+}
+
+#[async_trait]
+impl SessionStore for PostgresSessionStore {
+    /// Picks a new id until it does not collide with an existing session.
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        let mut conn = self.db_pool.get().await.map_err(backend_error)?;
+
+        loop {
+            let (id, data, expiry_date) = record_to_row(record)?;
+            let inserted = diesel::insert_into(session::table)
+                .values((
+                    session::id.eq(id),
+                    session::data.eq(data),
+                    session::expiry_date.eq(expiry_date),
+                ))
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .await
+                .map_err(backend_error)?;
+
+            if inserted == 1 {
+                return Ok(())
+            }
+            record.id = Id::default();
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        let mut conn = self.db_pool.get().await.map_err(backend_error)?;
+        let (id, data, expiry_date) = record_to_row(record)?;
+
+        diesel::insert_into(session::table)
+            .values((
+                session::id.eq(id),
+                session::data.eq(data),
+                session::expiry_date.eq(expiry_date),
+            ))
+            .on_conflict(session::id)
+            .do_update()
+            .set((
+                session::data.eq(excluded(session::data)),
+                session::expiry_date.eq(excluded(session::expiry_date)),
+            ))
+            .execute(&mut conn)
+            .await
+            .map_err(backend_error)?;
+
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let mut conn = self.db_pool.get().await.map_err(backend_error)?;
+
+        let data = session::table
+            .filter(session::id.eq(session_id.to_string()))
+            .filter(session::expiry_date.gt(Utc::now()))
+            .select(session::data)
+            .get_result::<String>(&mut conn)
+            .await
+            .optional()
+            .map_err(backend_error)?;
+
+        data.map(|data| serde_json::from_str::<Record>(&data))
+            .transpose()
+            .map_err(|err| session_store::Error::Decode(err.to_string()))
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        let mut conn = self.db_pool.get().await.map_err(backend_error)?;
+
+        diesel::delete(session::table)
+            .filter(session::id.eq(session_id.to_string()))
+            .execute(&mut conn)
+            .await
+            .map_err(backend_error)?;
+
+        Ok(())
+    }
+}
+
+/// Logs the user out on every device, including logins that still wait for the second factor.
+/// The user id is read from the session data as axum-login and the two-factor login store it.
+pub async fn delete_sessions_of_user(u_id: i32, conn: &mut DBConnection) -> APIResult<()> {
+    diesel::sql_query("DELETE FROM session \
+        WHERE (data::jsonb #>> '{data,\"axum-login.data\",user_id}') = $1 \
+        OR (data::jsonb #>> '{data,two_factor_pending,user_id}') = $1")
+        .bind::<Text, _>(u_id.to_string())
+        .execute(&mut conn.0)
+        .await
+        .map_err(APIError::internal)?;
+    Ok(())
+}
+
+pub fn session_layer(store: PostgresSessionStore) -> SessionManagerLayer<PostgresSessionStore> {
+    SessionManagerLayer::new(store)
+        .with_name(session_cookie_name())
+        .with_secure(session_secure())
+        .with_same_site(session_same_site())
+        .with_expiry(Expiry::OnInactivity(time::Duration::days(session_expiry_days())))
+}
+
+pub async fn start_session_cleanup(store: PostgresSessionStore) {
+    let mut interval = tokio::time::interval(Duration::from_secs(session_cleanup_interval_secs()));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = store.delete_expired().await {
+            warn!("Session cleanup failed: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use diesel_async::AsyncPgConnection;
+    use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+    use time::OffsetDateTime;
+    use super::*;
+
+    async fn build_store() -> PostgresSessionStore {
+        let db_url = std::env::var("DATABASE_URL").unwrap();
+        let config = AsyncDieselConnectionManager::<AsyncPgConnection>::new(db_url);
+        let pool = DBPool::builder().build(config).await.unwrap();
+        PostgresSessionStore::new(pool)
+    }
+
+    fn build_record(expiry_date: OffsetDateTime) -> Record {
+        let mut data = HashMap::new();
+        data.insert("axum-login.data".to_string(), serde_json::json!({"user_id": 42}));
+        Record { id: Id::default(), data, expiry_date }
+    }
+
+    async fn is_stored(store: &PostgresSessionStore, record: &Record) -> bool {
+        let mut conn = store.db_pool.get().await.unwrap();
+        session::table
+            .filter(session::id.eq(record.id.to_string()))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await
+            .unwrap() > 0
+    }
+
+    /// Needs a migrated database in `DATABASE_URL` and is skipped without one.
+    #[tokio::test]
+    async fn session_survives_a_new_store() {
+        if std::env::var("DATABASE_URL").is_err() {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return
+        }
+
+        let record = build_record(OffsetDateTime::now_utc() + time::Duration::hours(1));
+        let store = build_store().await;
+        store.save(&record).await.unwrap();
+        drop(store);
+
+        let store = build_store().await;
+        let loaded = SessionStore::load(&store, &record.id).await.unwrap();
+        store.delete(&record.id).await.unwrap();
+
+        assert_eq!(loaded, Some(record));
+    }
+
+    /// Needs a migrated database in `DATABASE_URL` and is skipped without one.
+    #[tokio::test]
+    async fn delete_expired_only_removes_expired_sessions() {
+        if std::env::var("DATABASE_URL").is_err() {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return
+        }
+
+        let store = build_store().await;
+        let expired = build_record(OffsetDateTime::now_utc() - time::Duration::hours(1));
+        let active = build_record(OffsetDateTime::now_utc() + time::Duration::hours(1));
+        store.save(&expired).await.unwrap();
+        store.save(&active).await.unwrap();
+
+        let deleted = store.delete_expired().await.unwrap();
+        let expired_stored = is_stored(&store, &expired).await;
+        let active_stored = is_stored(&store, &active).await;
+        store.delete(&active.id).await.unwrap();
+
+        assert!(deleted >= 1);
+        assert!(!expired_stored);
+        assert!(active_stored);
+    }
+
+    /// Needs a migrated database in `DATABASE_URL` and is skipped without one.
+    #[tokio::test]
+    async fn delete_sessions_of_user_only_removes_their_sessions() {
+        if std::env::var("DATABASE_URL").is_err() {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return
+        }
+
+        let store = build_store().await;
+        let expiry_date = OffsetDateTime::now_utc() + time::Duration::hours(1);
+        let mut login = build_record(expiry_date);
+        login.data.insert("axum-login.data".to_string(), serde_json::json!({"user_id": -7, "auth_hash": [1, 2]}));
+        let mut pending = build_record(expiry_date);
+        pending.data = HashMap::from([("two_factor_pending".to_string(), serde_json::json!({"user_id": -7, "expires": 0}))]);
+        let other = build_record(expiry_date);
+        for record in [&login, &pending, &other] {
+            store.save(record).await.unwrap();
+        }
+
+        let mut conn = DBConnection(store.db_pool.get_owned().await.unwrap());
+        delete_sessions_of_user(-7, &mut conn).await.unwrap();
+        let login_stored = is_stored(&store, &login).await;
+        let pending_stored = is_stored(&store, &pending).await;
+        let other_stored = is_stored(&store, &other).await;
+        store.delete(&other.id).await.unwrap();
+
+        assert!(!login_stored);
+        assert!(!pending_stored);
+        assert!(other_stored);
+    }
+}